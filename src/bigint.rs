@@ -0,0 +1,588 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// A minimal arbitrary-precision signed integer, used when integer arithmetic overflows an i64.
+///
+/// The magnitude is stored as little-endian base 2^32 limbs with no trailing zero limbs, so zero is
+/// the empty vector and is never negative.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+
+        Self { negative, limbs }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// The number of bits in the magnitude, which is 0 for zero.
+    pub fn bit_len(&self) -> u64 {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// Parses a string of digits in the given radix (no sign).
+    pub fn parse_radix(s: &str, radix: u32) -> Option<Self> {
+        if s.is_empty() {
+            return None;
+        }
+
+        let mut limbs = vec![];
+        for c in s.chars() {
+            let digit = c.to_digit(radix)?;
+            mul_small_add(&mut limbs, radix, digit);
+        }
+
+        Some(Self::from_parts(false, limbs))
+    }
+
+    /// Returns the value as an i64 if it is in range.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// Converts an integral float exactly. None if it isn't finite and integral.
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+
+        // n is mantissa * 2^exp, with the implicit leading bit of normal numbers included. Integral
+        // subnormals are all zero.
+        let bits = n.to_bits();
+        let biased_exp = ((bits >> 52) & 0x7ff) as i32;
+        if biased_exp == 0 {
+            return Some(Self::from(0));
+        }
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let exp = biased_exp - 1075;
+
+        // mantissa fits in 53 bits, and since n is integral, shifting right only drops zeros
+        let magnitude = if exp >= 0 {
+            Self::from(mantissa as i64).shl(exp as u32)
+        } else {
+            Self::from((mantissa >> -exp) as i64)
+        };
+
+        Some(if n < 0.0 { magnitude.neg() } else { magnitude })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &limb| acc * 4294967296.0 + limb as f64);
+
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::from_parts(!self.negative, self.limbs.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self::from_parts(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+
+        // Signs differ: subtract the smaller magnitude from the larger one.
+        match cmp_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => {
+                Self::from_parts(other.negative, sub_magnitudes(&other.limbs, &self.limbs))
+            }
+            _ => Self::from_parts(self.negative, sub_magnitudes(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let t = a as u64 * b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+
+        Self::from_parts(self.negative != other.negative, limbs)
+    }
+
+    /// Truncating division, returning (quotient, remainder). The remainder takes the sign of the
+    /// dividend, matching i64's `/` and `%`. Returns None when dividing by zero.
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+
+        let (q, r) = div_rem_magnitudes(&self.limbs, &other.limbs);
+
+        Some((
+            Self::from_parts(self.negative != other.negative, q),
+            Self::from_parts(self.negative, r),
+        ))
+    }
+
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        self.div_rem(other).map(|(q, _)| q)
+    }
+
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        self.div_rem(other).map(|(_, r)| r)
+    }
+
+    /// Division rounding towards negative infinity. Returns None when dividing by zero.
+    pub fn div_floor(&self, other: &Self) -> Option<Self> {
+        let (q, r) = self.div_rem(other)?;
+
+        if !r.is_zero() && self.negative != other.negative {
+            Some(q.sub(&Self::from(1)))
+        } else {
+            Some(q)
+        }
+    }
+
+    // Bitwise operators act as if on infinite-width two's complement, like i64's do on 64 bits.
+
+    /// `!x`, i.e. `-x - 1`.
+    pub fn not(&self) -> Self {
+        self.neg().sub(&Self::from(1))
+    }
+
+    pub fn and(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub fn xor(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    fn bitwise(&self, other: &Self, op: fn(u32, u32) -> u32) -> Self {
+        // one extra limb so the sign bit is never part of the magnitude
+        let len = self.limbs.len().max(other.limbs.len()) + 1;
+        let a = to_twos_complement(self, len);
+        let b = to_twos_complement(other, len);
+
+        let limbs = a.iter().zip(&b).map(|(&a, &b)| op(a, b)).collect();
+        from_twos_complement(limbs)
+    }
+
+    pub fn shl(&self, n: u32) -> Self {
+        if self.is_zero() {
+            return Self::from(0);
+        }
+
+        let mut limbs = vec![0; (n / 32) as usize];
+        let bits = n % 32;
+
+        if bits == 0 {
+            limbs.extend_from_slice(&self.limbs);
+        } else {
+            let mut carry = 0;
+            for &limb in &self.limbs {
+                limbs.push((limb << bits) | carry);
+                carry = limb >> (32 - bits);
+            }
+            limbs.push(carry);
+        }
+
+        Self::from_parts(self.negative, limbs)
+    }
+
+    /// Arithmetic shift right, which rounds towards negative infinity.
+    pub fn shr(&self, n: u32) -> Self {
+        if self.negative {
+            // -x >> n == !((x - 1) >> n)
+            return self.neg().sub(&Self::from(1)).shr(n).not();
+        }
+
+        let skip = (n / 32) as usize;
+        let bits = n % 32;
+        let limbs = self.limbs.get(skip..).unwrap_or(&[]);
+
+        let shifted = if bits == 0 {
+            limbs.to_vec()
+        } else {
+            (0..limbs.len())
+                .map(|i| (limbs[i] >> bits) | (limbs.get(i + 1).unwrap_or(&0) << (32 - bits)))
+                .collect()
+        };
+
+        Self::from_parts(false, shifted)
+    }
+
+    pub fn pow(&self, mut exp: u32) -> Self {
+        // square-and-multiply, from the lowest bit of the exponent up
+        let mut result = Self::from(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        Self::from_parts(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off base 10^9 chunks, least significant first.
+        let mut chunks = vec![];
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            chunks.push(div_small(&mut limbs, 1_000_000_000));
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{chunk:09}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/* Helpers operating on little-endian magnitudes */
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &limb) in long.iter().enumerate() {
+        let t = limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(t as u32);
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+
+    result
+}
+
+// requires a >= b
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut t = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        if t < 0 {
+            t += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(t as u32);
+    }
+
+    result
+}
+
+fn to_twos_complement(n: &BigInt, len: usize) -> Vec<u32> {
+    let mut limbs = n.limbs.clone();
+    if !n.negative {
+        limbs.resize(len, 0);
+        return limbs;
+    }
+
+    // -x == !(x - 1)
+    limbs = sub_magnitudes(&limbs, &[1]);
+    limbs.resize(len, 0);
+    limbs.iter().map(|limb| !limb).collect()
+}
+
+fn from_twos_complement(limbs: Vec<u32>) -> BigInt {
+    match limbs.last() {
+        Some(top) if top >> 31 == 1 => {
+            let inverted = limbs.iter().map(|limb| !limb).collect::<Vec<_>>();
+            BigInt::from_parts(true, add_magnitudes(&inverted, &[1]))
+        }
+        _ => BigInt::from_parts(false, limbs),
+    }
+}
+
+// limbs = limbs * m + a
+fn mul_small_add(limbs: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for limb in limbs.iter_mut() {
+        let t = *limb as u64 * m as u64 + carry;
+        *limb = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        limbs.push(carry as u32);
+    }
+}
+
+// Divides limbs in place by d, returning the remainder.
+fn div_small(limbs: &mut Vec<u32>, d: u32) -> u32 {
+    let mut rem = 0u64;
+    for limb in limbs.iter_mut().rev() {
+        let t = (rem << 32) | *limb as u64;
+        *limb = (t / d as u64) as u32;
+        rem = t % d as u64;
+    }
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+
+    rem as u32
+}
+
+// Binary long division. Slow compared to Knuth's algorithm D, but simple, and plenty for the sizes
+// scripts deal with.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let mut q = a.to_vec();
+        let r = div_small(&mut q, b[0]);
+        return (q, vec![r]);
+    }
+
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = vec![];
+
+    for i in (0..a.len() * 32).rev() {
+        // r = r << 1 | bit i of a
+        mul_small_add(&mut r, 2, (a[i / 32] >> (i % 32)) & 1);
+
+        if cmp_magnitudes(&r, b) != Ordering::Less {
+            r = sub_magnitudes(&r, b);
+            while r.last() == Some(&0) {
+                r.pop();
+            }
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+
+    (q, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    fn big(s: &str) -> BigInt {
+        match s.strip_prefix('-') {
+            Some(s) => BigInt::parse_radix(s, 10).unwrap().neg(),
+            None => BigInt::parse_radix(s, 10).unwrap(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in [
+            "0",
+            "1",
+            "-1",
+            "4294967296",
+            "-18446744073709551616",
+            "123456789012345678901234567890",
+        ] {
+            assert_eq!(big(s).to_string(), s);
+        }
+
+        assert_eq!(BigInt::from(i64::MIN).to_string(), i64::MIN.to_string());
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-0").to_string(), "0");
+
+        assert_eq!(BigInt::from_f64(1e20), Some(big("100000000000000000000")));
+        assert_eq!(BigInt::from_f64(-4096.0), Some(big("-4096")));
+        assert_eq!(BigInt::from_f64(-0.0), Some(big("0")));
+        assert_eq!(BigInt::from_f64(f64::MAX).unwrap().to_f64(), f64::MAX);
+        assert_eq!(BigInt::from_f64(0.5), None);
+        assert_eq!(BigInt::from_f64(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn bit_len() {
+        assert_eq!(big("0").bit_len(), 0);
+        assert_eq!(big("-1").bit_len(), 1);
+        assert_eq!(big("4294967295").bit_len(), 32);
+        assert_eq!(big("4294967296").bit_len(), 33);
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            big("9223372036854775807").add(&big("1")).to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            big("-9223372036854775808").sub(&big("1")).to_string(),
+            "-9223372036854775809"
+        );
+        assert_eq!(big("5").add(&big("-7")).to_string(), "-2");
+        assert_eq!(big("-5").add(&big("7")).to_string(), "2");
+        assert_eq!(big("5").sub(&big("5")).to_string(), "0");
+        assert_eq!(
+            big("123456789012345678901234567890")
+                .mul(&big("-987654321098765432109876543210"))
+                .to_string(),
+            "-121932631137021795226185032733622923332237463801111263526900"
+        );
+    }
+
+    #[test]
+    fn division() {
+        let (q, r) = big("121932631137021795226185032733622923332237463801111263526901")
+            .div_rem(&big("987654321098765432109876543210"))
+            .unwrap();
+        assert_eq!(q.to_string(), "123456789012345678901234567890");
+        assert_eq!(r.to_string(), "1");
+
+        // truncates towards zero, remainder has the sign of the dividend
+        let (q, r) = big("-100000000000000000000").div_rem(&big("7")).unwrap();
+        assert_eq!(q.to_string(), "-14285714285714285714");
+        assert_eq!(r.to_string(), "-2");
+
+        assert!(big("1").div_rem(&BigInt::from(0)).is_none());
+
+        // floor division rounds down when the signs differ
+        assert_eq!(
+            big("-100000000000000000000")
+                .div_floor(&big("7"))
+                .unwrap()
+                .to_string(),
+            "-14285714285714285715"
+        );
+        assert_eq!(
+            big("100000000000000000000")
+                .div_floor(&big("-100000000000000000000"))
+                .unwrap()
+                .to_string(),
+            "-1"
+        );
+        assert!(big("1").div_floor(&BigInt::from(0)).is_none());
+    }
+
+    #[test]
+    fn bitwise() {
+        // compare against i64 on values that fit
+        for (a, b) in [(12, 10), (-12, 10), (12, -10), (-12, -10), (0, -1)] {
+            let (x, y) = (BigInt::from(a), BigInt::from(b));
+            assert_eq!(x.and(&y), BigInt::from(a & b));
+            assert_eq!(x.or(&y), BigInt::from(a | b));
+            assert_eq!(x.xor(&y), BigInt::from(a ^ b));
+            assert_eq!(x.not(), BigInt::from(!a));
+            assert_eq!(x.shl(3), BigInt::from(a << 3));
+            assert_eq!(x.shr(3), BigInt::from(a >> 3));
+        }
+
+        let x = big("-340282366920938463463374607431768211456"); // -2^128
+        assert_eq!(x.shr(127).to_string(), "-2");
+        assert_eq!(x.shr(200).to_string(), "-1");
+        assert_eq!(big("1").shl(128).neg(), x);
+        assert_eq!(big("1").shl(100).shr(99).to_string(), "2");
+        assert_eq!(x.and(&big("-1")), x);
+        assert_eq!(
+            x.or(&big("1")).to_string(),
+            "-340282366920938463463374607431768211455"
+        );
+        assert_eq!(x.xor(&x.not()).to_string(), "-1");
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(
+            big("2").pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(big("-3").pow(41).to_string(), "-36472996377170786403");
+        assert_eq!(big("-3").pow(0).to_string(), "1");
+        assert_eq!(big("0").pow(5).to_string(), "0");
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(big("-100000000000000000000") < big("-1"));
+        assert!(big("-1") < big("0"));
+        assert!(big("99999999999999999999") < big("100000000000000000000"));
+        assert_eq!(big("100000000000000000000"), big("100000000000000000000"));
+    }
+}
@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::error::ErrorState;
+use crate::eval::Value;
+use crate::exec::{Environment, Scope};
+use crate::expr::{ClassDecl, Method, MethodKind, TraitDecl};
+use crate::function::{Args, Function, Signature};
+
+/// A class value, made by a class declaration.
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    // the class's own methods of each kind, including its traits'; inherited ones are found
+    // through the superclass
+    methods: HashMap<(MethodKind, String), Rc<Function>>,
+}
+
+/// A trait value, made by a trait declaration, whose methods classes can include.
+pub struct Trait {
+    pub name: String,
+    methods: HashMap<(MethodKind, String), Rc<Function>>,
+}
+
+/// An object made by calling a class.
+pub struct Instance {
+    pub class: Rc<Class>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+
+// Not derived, since methods' closures may well contain the class itself.
+impl Debug for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Class")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// Not derived, since methods' closures may well contain the trait itself.
+impl Debug for Trait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Trait")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// Not derived, since fields may well contain the instance itself.
+impl Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Instance")
+            .field("class", &self.class.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Class {
+    /// Runs a class declaration, returning the class.
+    ///
+    /// Methods close over the scope the class is declared in. If there's a superclass, they close
+    /// over a scope nested in that one which binds `super` to it.
+    pub fn declare(decl: &ClassDecl, env: &mut Environment) -> Result<Value, ErrorState> {
+        let superclass = match &decl.superclass {
+            Some(e) => match e.eval(env)? {
+                Value::Class(class) => Some(class),
+                v => {
+                    return Err(ErrorState::runtime_error(
+                        format!("superclass must be a class, got {v}"),
+                        e.line,
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut env = Environment::with_scope(Scope::new_child(env.scope()));
+                env.insert("super".to_string(), Value::Class(superclass.clone()));
+                env.scope()
+            }
+            None => env.scope(),
+        };
+
+        let mut methods = make_methods(&decl.methods, closure);
+
+        // Trait methods fill in what the class doesn't define itself, as long as no other trait
+        // does too.
+        let mut from_trait: HashMap<(MethodKind, String), Rc<Trait>> = HashMap::new();
+        for e in &decl.traits {
+            let t = match e.eval(env)? {
+                Value::Trait(t) => t,
+                v => {
+                    return Err(ErrorState::runtime_error(
+                        format!("{} can only include traits, got {v}", decl.name),
+                        e.line,
+                    ))
+                }
+            };
+
+            for (key, method) in &t.methods {
+                if let Some(other) = from_trait.get(key) {
+                    return Err(ErrorState::runtime_error(
+                        format!(
+                            "traits {} and {} both define {} for class {}",
+                            other.name, t.name, key.1, decl.name
+                        ),
+                        e.line,
+                    ));
+                }
+                if methods.contains_key(key) {
+                    continue;
+                }
+
+                methods.insert(key.clone(), method.clone());
+                from_trait.insert(key.clone(), t.clone());
+            }
+        }
+
+        Ok(Value::Class(Rc::new(Class {
+            name: decl.name.clone(),
+            superclass,
+            methods,
+        })))
+    }
+
+    /// Looks up a method of the class, or failing that of its superclasses.
+    pub fn find(&self, kind: MethodKind, name: &str) -> Option<Rc<Function>> {
+        match self.methods.get(&(kind, name.to_string())) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find(kind, name),
+        }
+    }
+
+    /// Whether this is the given class or inherits from it.
+    pub fn is_subclass_of(&self, class: &Rc<Class>) -> bool {
+        std::ptr::eq(self, class.as_ref())
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.is_subclass_of(class))
+    }
+
+    /// Creates an instance, passing the arguments to its `init` method.
+    pub fn call(class: &Rc<Class>, args: Args, line: u32) -> Result<Value, ErrorState> {
+        let instance = Value::Instance(Rc::new(Instance {
+            class: class.clone(),
+            fields: RefCell::new(HashMap::new()),
+        }));
+
+        match class.find(MethodKind::Method, "init") {
+            Some(init) => {
+                init.bind(instance.clone()).call(args, line)?;
+            }
+            None => {
+                let signature = Signature {
+                    name: &class.name,
+                    params: vec![],
+                    variadic: false,
+                };
+                signature.bind(args, line)?;
+            }
+        }
+
+        Ok(instance)
+    }
+}
+
+impl Trait {
+    /// Runs a trait declaration, returning the trait. Its methods close over the scope it's
+    /// declared in.
+    pub fn declare(decl: &TraitDecl, env: &Environment) -> Value {
+        Value::Trait(Rc::new(Trait {
+            name: decl.name.clone(),
+            methods: make_methods(&decl.methods, env.scope()),
+        }))
+    }
+}
+
+// Makes the functions for the methods of a class or trait body.
+fn make_methods(
+    methods: &[Method],
+    closure: Rc<RefCell<Scope>>,
+) -> HashMap<(MethodKind, String), Rc<Function>> {
+    methods
+        .iter()
+        .map(|method| {
+            let f = Function {
+                decl: method.decl.clone(),
+                closure: closure.clone(),
+                initializer: method.kind == MethodKind::Method && method.name == "init",
+            };
+            ((method.kind, method.name.clone()), Rc::new(f))
+        })
+        .collect()
+}
+
+// Runs a getter, setter or other method for a property accessed on the given line.
+fn call_accessor(
+    accessor: &Function,
+    object: &Value,
+    args: Vec<Value>,
+    line: u32,
+) -> Result<Value, ErrorState> {
+    let args = Args {
+        positional: args,
+        named: vec![],
+    };
+    accessor
+        .bind(object.clone())
+        .call(args, line)
+        .map_err(|e| e.with_frame(line))
+}
+
+impl Instance {
+    /// The value of a field, without falling back to getters or methods.
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    /// Calls a method of the instance without arguments, for a use of it on the given line. None
+    /// means its class has no such method.
+    pub fn call_method(
+        self: &Rc<Self>,
+        name: &str,
+        line: u32,
+    ) -> Option<Result<Value, ErrorState>> {
+        let method = self.class.find(MethodKind::Method, name)?;
+        Some(call_accessor(
+            &method,
+            &Value::Instance(self.clone()),
+            vec![],
+            line,
+        ))
+    }
+}
+
+/// Reads `object.name`. On an instance that's a field, or else the result of a getter, or else a
+/// method bound to the instance. On a class it's a static method bound to the class.
+pub fn get_property(object: &Value, name: &str, line: u32) -> Result<Value, ErrorState> {
+    let undefined = || ErrorState::runtime_error(format!("undefined property {name}"), line);
+
+    match object {
+        Value::Instance(instance) => {
+            if let Some(v) = instance.fields.borrow().get(name) {
+                return Ok(v.clone());
+            }
+
+            let class = &instance.class;
+            if let Some(getter) = class.find(MethodKind::Getter, name) {
+                call_accessor(&getter, object, vec![], line)
+            } else if let Some(method) = class.find(MethodKind::Method, name) {
+                Ok(Value::Function(Rc::new(method.bind(object.clone()))))
+            } else {
+                Err(undefined())
+            }
+        }
+        Value::Class(class) => match class.find(MethodKind::Static, name) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(object.clone())))),
+            None => Err(undefined()),
+        },
+        v => Err(ErrorState::runtime_error(
+            format!("only instances and classes have properties, got {v}"),
+            line,
+        )),
+    }
+}
+
+/// Assigns `object.name = value`, through a setter if the instance's class has one, or else to a
+/// field.
+pub fn set_property(object: &Value, name: &str, value: Value, line: u32) -> Result<(), ErrorState> {
+    match object {
+        Value::Instance(instance) => {
+            match instance.class.find(MethodKind::Setter, name) {
+                Some(setter) => {
+                    call_accessor(&setter, object, vec![value], line)?;
+                }
+                None => {
+                    instance.fields.borrow_mut().insert(name.to_string(), value);
+                }
+            }
+            Ok(())
+        }
+        v => Err(ErrorState::runtime_error(
+            format!("only instances have fields, got {v}"),
+            line,
+        )),
+    }
+}
+
+/// Reads `super.name` in a method: the superclass's method (or getter) bound to `this`. In a
+/// static method, `this` is the class, so it's the superclass's static method.
+pub fn get_super(name: &str, env: &mut Environment, line: u32) -> Result<Value, ErrorState> {
+    // The parser only allows `super` in the methods of a class with a superclass, which close over
+    // both.
+    let Some(Value::Class(superclass)) = env.get(&"super".to_string()) else {
+        unreachable!("super is bound around methods");
+    };
+    let this = env.get(&"this".to_string()).unwrap();
+
+    let kinds: &[MethodKind] = match this {
+        Value::Class(_) => &[MethodKind::Static],
+        _ => &[MethodKind::Getter, MethodKind::Method],
+    };
+    for &kind in kinds {
+        if let Some(method) = superclass.find(kind, name) {
+            return match kind {
+                MethodKind::Getter => call_accessor(&method, &this, vec![], line),
+                _ => Ok(Value::Function(Rc::new(method.bind(this)))),
+            };
+        }
+    }
+
+    Err(ErrorState::runtime_error(
+        format!(
+            "undefined property {name} on superclass {}",
+            superclass.name
+        ),
+        line,
+    ))
+}
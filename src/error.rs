@@ -2,18 +2,26 @@
 
 use std::fmt::Display;
 
+use crate::eval::Value;
+
 /// Represents a possible errored state that results from running the interpreter.
 ///
 /// The interpreter can only return errors from one phase, because it won't procede to the next one
 /// if there are errors.
 ///
-/// The interpreter will try to produce as many scanner and parser errors at one time, but will stop
-/// execution at the first runtime error.
+/// The interpreter will try to produce as many scanner, parser and resolver errors at one time, but
+/// will stop execution at the first runtime error that isn't caught.
+///
+/// Runtime errors and thrown values carry the lines of the calls they have unwound through so far,
+/// innermost first.
 #[derive(Debug)]
 pub enum ErrorState {
     ScanErrs(Vec<Error>),
     ParseErrs(Vec<Error>),
-    RuntimeErr(Error),
+    ResolveErrs(Vec<Error>),
+    RuntimeErr(Error, Vec<u32>),
+    // a value from a `throw` statement, and the line it was thrown on
+    Thrown(Value, u32, Vec<u32>),
 }
 
 impl ErrorState {
@@ -25,23 +33,43 @@ impl ErrorState {
         Self::ParseErrs(vec![])
     }
 
+    pub fn new_resolver_state() -> Self {
+        Self::ResolveErrs(vec![])
+    }
+
     pub fn runtime_error(e: String, lineno: u32) -> Self {
-        Self::RuntimeErr(Error::runtime_error(e, lineno))
+        Self::RuntimeErr(Error::runtime_error(e, lineno), vec![])
+    }
+
+    pub fn thrown(v: Value, lineno: u32) -> Self {
+        Self::Thrown(v, lineno, vec![])
     }
 
     pub fn add(&mut self, e: Error) {
         match self {
             Self::ScanErrs(v) => v.push(e),
             Self::ParseErrs(v) => v.push(e),
-            Self::RuntimeErr(_) => (), // can't update RuntimeError
+            Self::ResolveErrs(v) => v.push(e),
+            Self::RuntimeErr(..) | Self::Thrown(..) => (), // can't update RuntimeError
         }
     }
 
+    /// Records that the error unwound out of a function called on the given line.
+    pub fn with_frame(mut self, lineno: u32) -> Self {
+        match &mut self {
+            Self::RuntimeErr(_, stack) | Self::Thrown(_, _, stack) => stack.push(lineno),
+            Self::ScanErrs(_) | Self::ParseErrs(_) | Self::ResolveErrs(_) => (),
+        }
+
+        self
+    }
+
     pub fn is_ok(&mut self) -> bool {
         match self {
             Self::ScanErrs(v) => v.is_empty(),
             Self::ParseErrs(v) => v.is_empty(),
-            Self::RuntimeErr(_) => false,
+            Self::ResolveErrs(v) => v.is_empty(),
+            Self::RuntimeErr(..) | Self::Thrown(..) => false,
         }
     }
 }
@@ -56,7 +84,10 @@ pub struct Error {
 enum ErrorMsg {
     Scan(String),
     Parse(String),
+    Resolve(String),
     Runtime(String),
+    // Something suspicious that doesn't stop the program from running.
+    Warning(String),
 }
 
 impl Error {
@@ -74,12 +105,40 @@ impl Error {
         }
     }
 
+    pub fn resolve_error(msg: String, line: u32) -> Self {
+        Self {
+            line,
+            err: ErrorMsg::Resolve(msg),
+        }
+    }
+
     pub fn runtime_error(msg: String, line: u32) -> Self {
         Self {
             line,
             err: ErrorMsg::Runtime(msg),
         }
     }
+
+    pub fn warning(msg: String, line: u32) -> Self {
+        Self {
+            line,
+            err: ErrorMsg::Warning(msg),
+        }
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        match &self.err {
+            ErrorMsg::Scan(msg)
+            | ErrorMsg::Parse(msg)
+            | ErrorMsg::Resolve(msg)
+            | ErrorMsg::Runtime(msg)
+            | ErrorMsg::Warning(msg) => msg,
+        }
+    }
 }
 
 impl Display for ErrorState {
@@ -95,13 +154,52 @@ impl Display for ErrorState {
                     writeln!(f, "{e}")?;
                 }
             }
-            ErrorState::RuntimeErr(e) => write!(f, "{e}")?,
+            ErrorState::ResolveErrs(errs) => {
+                for e in errs {
+                    writeln!(f, "{e}")?;
+                }
+            }
+            ErrorState::RuntimeErr(e, stack) => {
+                write!(f, "{e}")?;
+                write_stack(f, stack)?;
+            }
+            ErrorState::Thrown(v, line, stack) => {
+                write!(f, "[{line}]: uncaught exception: {v}")?;
+                write_stack(f, stack)?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// How many lines of a call stack are printed before the rest are summarized.
+const MAX_STACK_LINES: usize = 20;
+
+/// Prints the call stack, collapsing runs of calls from the same line (as in deep recursion) into
+/// one entry and summarizing anything past [`MAX_STACK_LINES`] entries.
+fn write_stack(f: &mut std::fmt::Formatter<'_>, stack: &[u32]) -> std::fmt::Result {
+    let mut printed = 0;
+    let mut rest = stack;
+    while let Some(&line) = rest.first() {
+        if printed == MAX_STACK_LINES {
+            let calls = if rest.len() == 1 { "call" } else { "calls" };
+            return write!(f, "\n    ... {} more {calls}", rest.len());
+        }
+
+        let run = rest.iter().take_while(|&&l| l == line).count();
+        write!(f, "\n    called from [{line}]")?;
+        if run > 1 {
+            write!(f, " ({run} times)")?;
+        }
+
+        printed += 1;
+        rest = &rest[run..];
+    }
+
+    Ok(())
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[{}]: {}", self.line, self.err)
@@ -113,7 +211,44 @@ impl Display for ErrorMsg {
         match self {
             ErrorMsg::Scan(msg) => write!(f, "scan error: {msg}"),
             ErrorMsg::Parse(msg) => write!(f, "parse error: {msg}"),
+            ErrorMsg::Resolve(msg) => write!(f, "resolve error: {msg}"),
             ErrorMsg::Runtime(msg) => write!(f, "runtime error: {msg}"),
+            ErrorMsg::Warning(msg) => write!(f, "warning: {msg}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime_error(stack: Vec<u32>) -> String {
+        ErrorState::RuntimeErr(Error::runtime_error("oops".to_string(), 1), stack).to_string()
+    }
+
+    #[test]
+    fn error_kinds() {
+        let e = Error::resolve_error("can't assign to const x".to_string(), 3);
+        assert_eq!(e.to_string(), "[3]: resolve error: can't assign to const x");
+        let e = Error::warning("unreachable match arm".to_string(), 4);
+        assert_eq!(e.to_string(), "[4]: warning: unreachable match arm");
+    }
+
+    #[test]
+    fn stack_traces() {
+        assert_eq!(
+            runtime_error(vec![2, 5]),
+            "[1]: runtime error: oops\n    called from [2]\n    called from [5]"
+        );
+        assert_eq!(
+            runtime_error(vec![2, 2, 2, 5]),
+            "[1]: runtime error: oops\n    called from [2] (3 times)\n    called from [5]"
+        );
+
+        // mutual recursion doesn't collapse, so it gets cut off instead
+        let stack: Vec<u32> = (0..5000).map(|i| i % 2 + 2).collect();
+        let trace = runtime_error(stack);
+        assert_eq!(trace.lines().count(), 1 + MAX_STACK_LINES + 1);
+        assert!(trace.ends_with("\n    ... 4980 more calls"));
+    }
+}
@@ -1,40 +1,730 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::rc::Rc;
 
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::bigint::BigInt;
+use crate::class::{self, Class, Instance, Trait};
 use crate::error::ErrorState;
 use crate::exec::Environment;
 use crate::expr::{BinOp, Expr, ExprData, UnaryOp};
+use crate::function::{Args, Function};
+use crate::generator::Generator;
+use crate::map::{Map, MapKey};
+use crate::native::NativeFn;
 
 #[derive(Clone, Debug)]
 pub enum Value {
-    Number(f32),
+    Number(f64),
+    Int(i64),
+    BigInt(BigInt),
     String(String),
     Boolean(bool),
     Nil,
+
+    // Lists are shared, so assigning one to another variable aliases it.
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    // the integers from start up to end, exclusive
+    Range(i64, i64),
+    Native(&'static NativeFn),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    Trait(Rc<Trait>),
+    Instance(Rc<Instance>),
+    Generator(Rc<Generator>),
+}
+
+impl Value {
+    /// nil and false are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    // `enclosing` holds the lists and maps being written around this value, so that one containing
+    // itself is written as [...] or {...} instead of recursing forever.
+    fn write(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        enclosing: &mut Vec<*const ()>,
+    ) -> std::fmt::Result {
         match self {
-            Value::Number(v) => write!(f, "{v}"),
+            Value::Number(v) => write!(f, "{}", format_number(*v)),
+            Value::Int(v) => write!(f, "{v}"),
+            Value::BigInt(v) => write!(f, "{v}"),
             Value::String(v) => write!(f, "{v}"),
             Value::Boolean(v) => write!(f, "{v}"),
             Value::Nil => write!(f, "nil"),
+            Value::List(l) => {
+                let ptr = Rc::as_ptr(l) as *const ();
+                if enclosing.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                enclosing.push(ptr);
+                write!(f, "[")?;
+                for (i, v) in l.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    v.write(f, enclosing)?;
+                }
+                enclosing.pop();
+                write!(f, "]")
+            }
+            Value::Map(m) => {
+                let ptr = Rc::as_ptr(m) as *const ();
+                if enclosing.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                enclosing.push(ptr);
+                write!(f, "{{")?;
+                for (i, (k, v)) in m.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", k.to_value())?;
+                    v.write(f, enclosing)?;
+                }
+                enclosing.pop();
+                write!(f, "}}")
+            }
+            Value::Range(start, end) => write!(f, "{start}..{end}"),
+            Value::Native(n) => write!(f, "<native fn {}>", n.name),
+            Value::Function(_) => write!(f, "<fn>"),
+            Value::Class(c) => write!(f, "<class {}>", c.name),
+            Value::Trait(t) => write!(f, "<trait {}>", t.name),
+            Value::Instance(i) => write!(f, "<{} instance>", i.class.name),
+            Value::Generator(_) => write!(f, "<generator>"),
         }
     }
 }
 
+/// Formats a number the way Lox prints it: integral values have no trailing `.0`, negative zero
+/// keeps its sign, and non-finite values are spelled `NaN`, `Infinity` and `-Infinity`. Like
+/// JavaScript, very large and very small magnitudes use exponent notation, e.g. `1e+21` and
+/// `1.5e-7`.
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 {
+            "Infinity".to_string()
+        } else {
+            "-Infinity".to_string()
+        }
+    } else if n != 0.0 && !(1e-6..1e21).contains(&n.abs()) {
+        // LowerExp also prints the shortest round-tripping digits, but leaves out the exponent's
+        // sign when it's positive.
+        let s = format!("{n:e}");
+        match s.split_once('e') {
+            Some((digits, exp)) if !exp.starts_with('-') => format!("{digits}e+{exp}"),
+            _ => s,
+        }
+    } else {
+        // f64's Display already prints the shortest round-tripping representation, and omits the
+        // fractional part for integral values (including -0).
+        format!("{n}")
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(a), Value::Number(b)) | (Value::Number(b), Value::Int(a)) => {
+                int_float_cmp(*a, *b) == Some(Ordering::Equal)
+            }
+            // BigInts are always out of i64 range, so they never equal an Int.
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::BigInt(a), Value::Number(b)) | (Value::Number(b), Value::BigInt(a)) => {
+                big_float_cmp(a, *b) == Some(Ordering::Equal)
+            }
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Nil, Value::Nil) => true,
+            // lists and maps compare by identity
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a, b), Value::Range(c, d)) => a == c && b == d,
+            (Value::Native(a), Value::Native(b)) => std::ptr::eq(*a, *b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Trait(a), Value::Trait(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Generator(a), Value::Generator(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
+impl Value {
+    /// Wraps an integer result, demoting it to an Int if it fits. This keeps BigInt values
+    /// strictly outside the i64 range.
+    fn from_bigint(n: BigInt) -> Self {
+        match n.to_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::BigInt(n),
+        }
+    }
+}
+
+/// The operands of a numeric binary operator after promotion: integers stay integers (widened to
+/// BigInts if either side is one), and an integer mixed with a float is converted to a float.
+enum Operands {
+    Ints(i64, i64),
+    BigInts(BigInt, BigInt),
+    Floats(f64, f64),
+}
+
+impl Operands {
+    fn promote(left: &Value, right: &Value) -> Option<Self> {
+        let operands = match (left, right) {
+            (Value::Int(a), Value::Int(b)) => Self::Ints(*a, *b),
+
+            (Value::BigInt(a), Value::BigInt(b)) => Self::BigInts(a.clone(), b.clone()),
+            (Value::BigInt(a), Value::Int(b)) => Self::BigInts(a.clone(), (*b).into()),
+            (Value::Int(a), Value::BigInt(b)) => Self::BigInts((*a).into(), b.clone()),
+
+            (Value::Int(a), Value::Number(b)) => Self::Floats(*a as f64, *b),
+            (Value::Number(a), Value::Int(b)) => Self::Floats(*a, *b as f64),
+            (Value::BigInt(a), Value::Number(b)) => Self::Floats(a.to_f64(), *b),
+            (Value::Number(a), Value::BigInt(b)) => Self::Floats(*a, b.to_f64()),
+            (Value::Number(a), Value::Number(b)) => Self::Floats(*a, *b),
+
+            _ => return None,
+        };
+
+        Some(operands)
+    }
+}
+
+/// Compares an integer with a float exactly, rather than rounding the integer to the nearest float,
+/// which would make 2^53 + 1 equal to 2^53. None if the float is NaN.
+fn int_float_cmp(a: i64, b: f64) -> Option<Ordering> {
+    // 2^63, the first float above the i64 range
+    const LIMIT: f64 = 9223372036854775808.0;
+
+    if b.is_nan() {
+        None
+    } else if b >= LIMIT {
+        Some(Ordering::Less)
+    } else if b < -LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        // in range, so the cast is exact, and the integers between them are ordered the same way
+        let t = b.trunc();
+        match a.cmp(&(t as i64)) {
+            Ordering::Equal => 0.0.partial_cmp(&(b - t)),
+            ord => Some(ord),
+        }
+    }
+}
+
+/// Compares a BigInt with a float exactly, like int_float_cmp. None if the float is NaN.
+fn big_float_cmp(a: &BigInt, b: f64) -> Option<Ordering> {
+    if b.is_nan() {
+        return None;
+    }
+    if b.is_infinite() {
+        return Some(if b > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        });
+    }
+
+    let floor = b.floor();
+    match a.cmp(&BigInt::from_f64(floor).unwrap()) {
+        Ordering::Equal => 0.0.partial_cmp(&(b - floor)),
+        ord => Some(ord),
+    }
+}
+
+// None if the values aren't both numbers, and Some(None) if they're unordered because one is NaN.
+fn numeric_cmp(left: &Value, right: &Value) -> Option<Option<Ordering>> {
+    let ord = match (left, right) {
+        (Value::Int(a), Value::Number(b)) => int_float_cmp(*a, *b),
+        (Value::Number(a), Value::Int(b)) => int_float_cmp(*b, *a).map(Ordering::reverse),
+        (Value::BigInt(a), Value::Number(b)) => big_float_cmp(a, *b),
+        (Value::Number(a), Value::BigInt(b)) => big_float_cmp(b, *a).map(Ordering::reverse),
+        _ => match Operands::promote(left, right)? {
+            Operands::Ints(a, b) => Some(a.cmp(&b)),
+            Operands::BigInts(a, b) => Some(a.cmp(&b)),
+            Operands::Floats(a, b) => a.partial_cmp(&b),
+        },
+    };
+
+    Some(ord)
+}
+
+// Comparisons involving NaN are false.
+fn compare(
+    left: &Value,
+    right: &Value,
+    line: u32,
+    cmp: fn(Ordering) -> bool,
+) -> Result<Value, ErrorState> {
+    match numeric_cmp(left, right) {
+        Some(ord) => Ok(Value::Boolean(ord.is_some_and(cmp))),
+        None => Err(ErrorState::runtime_error(
+            "can only compare numbers".into(),
+            line,
+        )),
+    }
+}
+
+/// The implementations of an arithmetic operator for each kind of operand. When the i64 version
+/// overflows, the operation is redone on BigInts.
+struct ArithOp {
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(&BigInt, &BigInt) -> Option<BigInt>,
+    float: fn(f64, f64) -> f64,
+}
+
+const ADD: ArithOp = ArithOp {
+    int: i64::checked_add,
+    big: |a, b| Some(a.add(b)),
+    float: |a, b| a + b,
+};
+
+const SUB: ArithOp = ArithOp {
+    int: i64::checked_sub,
+    big: |a, b| Some(a.sub(b)),
+    float: |a, b| a - b,
+};
+
+const MULT: ArithOp = ArithOp {
+    int: i64::checked_mul,
+    big: |a, b| Some(a.mul(b)),
+    float: |a, b| a * b,
+};
+
+// Integer division truncates towards zero.
+const DIV: ArithOp = ArithOp {
+    int: i64::checked_div,
+    big: BigInt::div,
+    float: |a, b| a / b,
+};
+
+// The result takes the sign of the dividend, as in C.
+const MOD: ArithOp = ArithOp {
+    int: i64::checked_rem,
+    big: BigInt::rem,
+    float: |a, b| a % b,
+};
+
+// Rounds towards negative infinity rather than zero.
+const FLOOR_DIV: ArithOp = ArithOp {
+    int: |a, b| {
+        let q = a.checked_div(b)?;
+        if a % b != 0 && (a < 0) != (b < 0) {
+            Some(q - 1)
+        } else {
+            Some(q)
+        }
+    },
+    big: BigInt::div_floor,
+    float: |a, b| (a / b).floor(),
+};
+
+fn arithmetic(
+    left: &Value,
+    right: &Value,
+    line: u32,
+    op: ArithOp,
+    err: &str,
+) -> Result<Value, ErrorState> {
+    // Unlike floats, integer division and modulo error out on a zero divisor rather than producing
+    // inf/NaN. The BigInt implementations signal this by returning None.
+    let division_by_zero = || ErrorState::runtime_error("division by zero".into(), line);
+
+    match Operands::promote(left, right) {
+        Some(Operands::Ints(a, b)) => match (op.int)(a, b) {
+            Some(n) => Ok(Value::Int(n)),
+            None if b == 0 => Err(division_by_zero()),
+            None => (op.big)(&a.into(), &b.into())
+                .map(Value::from_bigint)
+                .ok_or_else(division_by_zero),
+        },
+        Some(Operands::BigInts(a, b)) => (op.big)(&a, &b)
+            .map(Value::from_bigint)
+            .ok_or_else(division_by_zero),
+        Some(Operands::Floats(a, b)) => Ok(Value::Number((op.float)(a, b))),
+        None => Err(ErrorState::runtime_error(err.into(), line)),
+    }
+}
+
+// An integer raised to a non-negative integer power stays an integer. A negative exponent gives a
+// float, since the result is (almost always) a fraction.
+fn power(left: &Value, right: &Value, line: u32) -> Result<Value, ErrorState> {
+    let (base, exp) = match Operands::promote(left, right) {
+        Some(Operands::Ints(a, b)) => {
+            if let Some(n) = u32::try_from(b).ok().and_then(|b| a.checked_pow(b)) {
+                return Ok(Value::Int(n));
+            }
+            (BigInt::from(a), BigInt::from(b))
+        }
+        Some(Operands::BigInts(a, b)) => (a, b),
+        Some(Operands::Floats(a, b)) => return Ok(Value::Number(a.powf(b))),
+        None => {
+            return Err(ErrorState::runtime_error(
+                "can only raise numbers to a power".into(),
+                line,
+            ))
+        }
+    };
+
+    if exp < BigInt::from(0) {
+        return Ok(Value::Number(base.to_f64().powf(exp.to_f64())));
+    }
+
+    // These bases stay small however large the exponent is.
+    if let Some(b @ -1..=1) = base.to_i64() {
+        let odd = !exp.and(&BigInt::from(1)).is_zero();
+        let n = match b {
+            0 if !exp.is_zero() => 0,
+            -1 if odd => -1,
+            _ => 1,
+        };
+        return Ok(Value::Int(n));
+    }
+
+    let exp = exp
+        .to_i64()
+        .and_then(|e| u32::try_from(e).ok())
+        .ok_or_else(|| ErrorState::runtime_error(format!("exponent {exp} is too large"), line))?;
+
+    // a base of b bits raised to exp has more than (b - 1) * exp bits
+    let min_bits = base.bit_len().saturating_sub(1).saturating_mul(exp as u64);
+    if min_bits >= MAX_RESULT_BITS {
+        return Err(too_large("**", line));
+    }
+
+    Ok(Value::from_bigint(base.pow(exp)))
+}
+
+/// The most bits `**` and `<<` will produce, since a big enough exponent or shift count would
+/// otherwise run out of time or memory.
+const MAX_RESULT_BITS: u64 = 1 << 16;
+
+fn too_large(op: &str, line: u32) -> ErrorState {
+    ErrorState::runtime_error(
+        format!("result of {op} would have more than {MAX_RESULT_BITS} bits"),
+        line,
+    )
+}
+
+// Bitwise operators accept integral floats, which are converted to integers.
+fn as_integer(v: &Value) -> Option<Value> {
+    match v {
+        Value::Int(_) | Value::BigInt(_) => Some(v.clone()),
+        // in range and integral, so the cast is exact
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 9.2e18 => Some(Value::Int(*n as i64)),
+        _ => None,
+    }
+}
+
+fn not_an_integer(op: &str, v: &Value, line: u32) -> ErrorState {
+    ErrorState::runtime_error(
+        format!("{op} can only be applied to integers, got {v}"),
+        line,
+    )
+}
+
+fn integer_operands(
+    left: &Value,
+    right: &Value,
+    line: u32,
+    op: &str,
+) -> Result<Operands, ErrorState> {
+    let left = as_integer(left).ok_or_else(|| not_an_integer(op, left, line))?;
+    let right = as_integer(right).ok_or_else(|| not_an_integer(op, right, line))?;
+
+    // both sides are Ints or BigInts, so they can't promote to floats
+    Ok(Operands::promote(&left, &right).unwrap())
+}
+
+/// A bitwise operator on i64s and on BigInts. Neither can overflow.
+struct BitOp {
+    name: &'static str,
+    int: fn(i64, i64) -> i64,
+    big: fn(&BigInt, &BigInt) -> BigInt,
+}
+
+const BIT_AND: BitOp = BitOp {
+    name: "&",
+    int: |a, b| a & b,
+    big: BigInt::and,
+};
+
+const BIT_OR: BitOp = BitOp {
+    name: "|",
+    int: |a, b| a | b,
+    big: BigInt::or,
+};
+
+const BIT_XOR: BitOp = BitOp {
+    name: "^",
+    int: |a, b| a ^ b,
+    big: BigInt::xor,
+};
+
+fn bitwise(left: &Value, right: &Value, line: u32, op: BitOp) -> Result<Value, ErrorState> {
+    match integer_operands(left, right, line, op.name)? {
+        Operands::Ints(a, b) => Ok(Value::Int((op.int)(a, b))),
+        Operands::BigInts(a, b) => Ok(Value::from_bigint((op.big)(&a, &b))),
+        Operands::Floats(..) => unreachable!(),
+    }
+}
+
+// Left shifts overflow into BigInts like other integer arithmetic. Right shifts are arithmetic, so
+// they round towards negative infinity.
+fn shift(left: &Value, right: &Value, line: u32, is_left: bool) -> Result<Value, ErrorState> {
+    let op = if is_left { "<<" } else { ">>" };
+
+    let (a, n) = match integer_operands(left, right, line, op)? {
+        Operands::Ints(a, n) if (0..64).contains(&n) => {
+            if !is_left {
+                return Ok(Value::Int(a >> n));
+            }
+            if (a << n) >> n == a {
+                return Ok(Value::Int(a << n));
+            }
+            (BigInt::from(a), BigInt::from(n))
+        }
+        Operands::Ints(a, n) => (BigInt::from(a), BigInt::from(n)),
+        Operands::BigInts(a, n) => (a, n),
+        Operands::Floats(..) => unreachable!(),
+    };
+
+    if n < BigInt::from(0) {
+        return Err(ErrorState::runtime_error(
+            format!("negative shift count {n}"),
+            line,
+        ));
+    }
+
+    let count = n.to_i64().and_then(|n| u32::try_from(n).ok());
+    let result = if is_left {
+        let count = count.ok_or_else(|| {
+            ErrorState::runtime_error(format!("shift count {n} is too large"), line)
+        })?;
+        if !a.is_zero() && a.bit_len() + count as u64 > MAX_RESULT_BITS {
+            return Err(too_large("<<", line));
+        }
+        a.shl(count)
+    } else {
+        // shifting right by more than the width leaves just the sign
+        a.shr(count.unwrap_or(u32::MAX))
+    };
+
+    Ok(Value::from_bigint(result))
+}
+
+/// Resolves an index into a sequence of the given length. Negative indices count back from the
+/// end.
+pub fn resolve_index(index: &Value, len: usize, line: u32) -> Result<usize, ErrorState> {
+    let i = match index {
+        Value::Int(i) => *i,
+        // BigInts are always out of bounds
+        Value::BigInt(_) => i64::MAX,
+        v => {
+            return Err(ErrorState::runtime_error(
+                format!("index must be an integer, got {v}"),
+                line,
+            ))
+        }
+    };
+
+    let resolved = if i < 0 { i + len as i64 } else { i };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(ErrorState::runtime_error(
+            format!("index {index} out of bounds for length {len}"),
+            line,
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+fn binary(op: BinOp, left_val: Value, right_val: Value, line: u32) -> Result<Value, ErrorState> {
+    match op {
+        BinOp::Comma => Ok(right_val),
+
+        BinOp::Eq => Ok(Value::Boolean(left_val == right_val)),
+        BinOp::Neq => Ok(Value::Boolean(left_val != right_val)),
+
+        BinOp::Gt => compare(&left_val, &right_val, line, Ordering::is_gt),
+        BinOp::GtEq => compare(&left_val, &right_val, line, Ordering::is_ge),
+        BinOp::Lt => compare(&left_val, &right_val, line, Ordering::is_lt),
+        BinOp::LtEq => compare(&left_val, &right_val, line, Ordering::is_le),
+
+        BinOp::Add => {
+            if let (Value::String(a), Value::String(b)) = (&left_val, &right_val) {
+                Ok(Value::String(format!("{a}{b}")))
+            } else {
+                arithmetic(
+                    &left_val,
+                    &right_val,
+                    line,
+                    ADD,
+                    "can only add numbers or strings",
+                )
+            }
+        }
+        BinOp::Sub => arithmetic(
+            &left_val,
+            &right_val,
+            line,
+            SUB,
+            "can only subtract numbers",
+        ),
+        BinOp::Mult => arithmetic(
+            &left_val,
+            &right_val,
+            line,
+            MULT,
+            "can only multiply numbers",
+        ),
+        BinOp::Div => arithmetic(&left_val, &right_val, line, DIV, "can only divide numbers"),
+        BinOp::Mod => arithmetic(
+            &left_val,
+            &right_val,
+            line,
+            MOD,
+            "can only take the modulo of numbers",
+        ),
+        BinOp::FloorDiv => arithmetic(
+            &left_val,
+            &right_val,
+            line,
+            FLOOR_DIV,
+            "can only divide numbers",
+        ),
+        BinOp::Pow => power(&left_val, &right_val, line),
+
+        BinOp::BitAnd => bitwise(&left_val, &right_val, line, BIT_AND),
+        BinOp::BitOr => bitwise(&left_val, &right_val, line, BIT_OR),
+        BinOp::BitXor => bitwise(&left_val, &right_val, line, BIT_XOR),
+        BinOp::Shl => shift(&left_val, &right_val, line, true),
+        BinOp::Shr => shift(&left_val, &right_val, line, false),
+
+        BinOp::Range => range(&left_val, &right_val, line),
+    }
+}
+
+fn range(start: &Value, end: &Value, line: u32) -> Result<Value, ErrorState> {
+    match integer_operands(start, end, line, "..")? {
+        Operands::Ints(start, end) => Ok(Value::Range(start, end)),
+        _ => Err(ErrorState::runtime_error(
+            format!("range bounds {start} and {end} are too large"),
+            line,
+        )),
+    }
+}
+
+fn get_index(target: &Value, index: &Value, line: u32) -> Result<Value, ErrorState> {
+    match target {
+        Value::List(l) => {
+            let l = l.borrow();
+            let i = resolve_index(index, l.len(), line)?;
+            Ok(l[i].clone())
+        }
+        // Strings are indexed by grapheme cluster, not byte, so an accented letter is one element
+        // however it's encoded.
+        Value::String(s) => {
+            let i = resolve_index(index, s.graphemes(true).count(), line)?;
+            Ok(Value::String(s.graphemes(true).nth(i).unwrap().to_string()))
+        }
+        Value::Map(m) => m
+            .borrow()
+            .get(&map_key(index, line)?)
+            .cloned()
+            .ok_or_else(|| ErrorState::runtime_error(format!("key {index} not found"), line)),
+        v => Err(ErrorState::runtime_error(
+            format!("can only index lists, maps and strings, got {v}"),
+            line,
+        )),
+    }
+}
+
+fn set_index(target: &Value, index: &Value, value: Value, line: u32) -> Result<(), ErrorState> {
+    match target {
+        Value::List(l) => {
+            let mut l = l.borrow_mut();
+            let i = resolve_index(index, l.len(), line)?;
+            l[i] = value;
+            Ok(())
+        }
+        Value::Map(m) => {
+            m.borrow_mut().insert(map_key(index, line)?, value);
+            Ok(())
+        }
+        v => Err(ErrorState::runtime_error(
+            format!("can only assign to list or map elements, got {v}"),
+            line,
+        )),
+    }
+}
+
+/// Applies `op` to the current value of a variable or indexed element and stores the result,
+/// returning the (old, new) values. The target's sub-expressions are evaluated once, before the
+/// operand.
+fn update(
+    op: BinOp,
+    target: &Expr,
+    operand: impl FnOnce(&mut Environment) -> Result<Value, ErrorState>,
+    line: u32,
+    state: &mut Environment,
+) -> Result<(Value, Value), ErrorState> {
+    match &target.data {
+        ExprData::Identifier(id) => {
+            let old = state.get(id).ok_or_else(|| {
+                ErrorState::runtime_error(format!("undefined variable {id}"), line)
+            })?;
+            let new = binary(op, old.clone(), operand(state)?, line)?;
+
+            state.assign(id, new.clone(), line)?;
+            Ok((old, new))
+        }
+        ExprData::Index(target, index) => {
+            let target = target.eval(state)?;
+            let index = index.eval(state)?;
+
+            let old = get_index(&target, &index, line)?;
+            let new = binary(op, old.clone(), operand(state)?, line)?;
+
+            set_index(&target, &index, new.clone(), line)?;
+            Ok((old, new))
+        }
+        ExprData::Get(object, name) => {
+            let object = object.eval(state)?;
+
+            let old = class::get_property(&object, name, line)?;
+            let new = binary(op, old.clone(), operand(state)?, line)?;
+
+            class::set_property(&object, name, new.clone(), line)?;
+            Ok((old, new))
+        }
+        _ => panic!("expected identifier, index or property"),
+    }
+}
+
+pub fn map_key(k: &Value, line: u32) -> Result<MapKey, ErrorState> {
+    MapKey::from_value(k).map_err(|e| ErrorState::runtime_error(e, line))
+}
+
 impl Expr {
     pub fn eval(&self, state: &mut Environment) -> Result<Value, ErrorState> {
         self.data.eval(self.line, state)
@@ -48,109 +738,30 @@ impl ExprData {
                 let left_val = left_expr.eval(state)?;
                 let right_val = right_expr.eval(state)?;
 
-                match op {
-                    BinOp::Eq => Ok(Value::Boolean(left_val == right_val)),
-                    BinOp::Neq => Ok(Value::Boolean(left_val != right_val)),
-
-                    BinOp::Gt => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Boolean(a > b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only compare numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::GtEq => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Boolean(a >= b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only compare numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::Lt => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Boolean(a < b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only compare numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::LtEq => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Boolean(a <= b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only compare numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-
-                    BinOp::Add => {
-                        if let (Value::Number(a), Value::Number(b)) = (&left_val, &right_val) {
-                            Ok(Value::Number(a + b))
-                        } else if let (Value::String(a), Value::String(b)) = (left_val, right_val) {
-                            Ok(Value::String(format!("{a}{b}")))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only add numbers or strings".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::Sub => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Number(a - b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only subtract numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::Div => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Number(a / b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only divide numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                    BinOp::Mult => {
-                        if let (Value::Number(a), Value::Number(b)) = (left_val, right_val) {
-                            Ok(Value::Number(a * b))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "can only multiply numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
-                }
+                binary(*op, left_val, right_val, line)
             }
 
             Self::Unary(op, e) => {
                 let val = e.eval(state)?;
                 match op {
-                    UnaryOp::Negative => {
-                        if let Value::Number(n) = val {
-                            Ok(Value::Number(-n))
-                        } else {
-                            Err(ErrorState::runtime_error(
-                                "- can only be applied to numbers".into(),
-                                line,
-                            ))
-                        }
-                    }
+                    UnaryOp::Negative => match val {
+                        Value::Number(n) => Ok(Value::Number(-n)),
+                        Value::Int(n) => Ok(match n.checked_neg() {
+                            Some(n) => Value::Int(n),
+                            None => Value::from_bigint(BigInt::from(n).neg()),
+                        }),
+                        Value::BigInt(n) => Ok(Value::from_bigint(n.neg())),
+                        _ => Err(ErrorState::runtime_error(
+                            "- can only be applied to numbers".into(),
+                            line,
+                        )),
+                    },
+                    UnaryOp::BitNot => match as_integer(&val) {
+                        Some(Value::Int(n)) => Ok(Value::Int(!n)),
+                        Some(Value::BigInt(n)) => Ok(Value::from_bigint(n.not())),
+                        _ => Err(not_an_integer("~", &val, line)),
+                    },
+                    UnaryOp::ToString => Ok(Value::String(val.to_string())),
                     UnaryOp::Inverse => {
                         if let Value::Boolean(b) = val {
                             Ok(Value::Boolean(!b))
@@ -164,12 +775,273 @@ impl ExprData {
                 }
             }
 
-            Self::Identifier(id) => Ok(state.get(id).unwrap_or(Value::Nil)),
+            Self::Call(callee, args, named) => {
+                let callee = callee.eval(state)?;
+
+                let mut positional = Vec::with_capacity(args.len());
+                for arg in args {
+                    positional.push(arg.eval(state)?);
+                }
+                let mut named_vals = Vec::with_capacity(named.len());
+                for (name, arg) in named {
+                    named_vals.push((name.clone(), arg.eval(state)?));
+                }
+                let args = Args {
+                    positional,
+                    named: named_vals,
+                };
+
+                match callee {
+                    Value::Native(native) => native.call(args, line),
+                    Value::Function(f) => f.call(args, line).map_err(|e| e.with_frame(line)),
+                    Value::Class(c) => Class::call(&c, args, line).map_err(|e| e.with_frame(line)),
+                    v => Err(ErrorState::runtime_error(
+                        format!("can only call functions, got {v}"),
+                        line,
+                    )),
+                }
+            }
+
+            Self::Index(target, index) => {
+                let target = target.eval(state)?;
+                let index = index.eval(state)?;
+
+                get_index(&target, &index, line)
+            }
+
+            Self::IndexSet(target, index, value) => {
+                let target = target.eval(state)?;
+                let index = index.eval(state)?;
+                let value = value.eval(state)?;
+
+                set_index(&target, &index, value.clone(), line)?;
+                Ok(value)
+            }
+
+            Self::Assign(id, value) => {
+                let value = value.eval(state)?;
+
+                state.assign(id, value.clone(), line)?;
+                Ok(value)
+            }
+
+            Self::CompoundAssign(op, target, value) => {
+                let (_, new) = update(*op, target, |state| value.eval(state), line, state)?;
+                Ok(new)
+            }
+
+            Self::PostfixUpdate(op, target) => {
+                let (old, _) = update(*op, target, |_| Ok(Value::Int(1)), line, state)?;
+                Ok(old)
+            }
+
+            Self::Ternary(cond, then_expr, else_expr) => {
+                if cond.eval(state)?.is_truthy() {
+                    then_expr.eval(state)
+                } else {
+                    else_expr.eval(state)
+                }
+            }
+
+            Self::ListLiteral(elements) => {
+                let mut vals = Vec::with_capacity(elements.len());
+                for e in elements {
+                    vals.push(e.eval(state)?);
+                }
+
+                Ok(Value::List(Rc::new(RefCell::new(vals))))
+            }
+
+            Self::MapLiteral(entries) => {
+                let mut map = Map::new();
+                for (k, v) in entries {
+                    let k = k.eval(state)?;
+                    let v = v.eval(state)?;
+                    map.insert(map_key(&k, line)?, v);
+                }
+
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+
+            Self::Lambda(decl) => Ok(Value::Function(Rc::new(Function {
+                decl: decl.clone(),
+                closure: state.scope(),
+                initializer: false,
+            }))),
+
+            Self::Get(object, name) => {
+                let object = object.eval(state)?;
+                class::get_property(&object, name, line)
+            }
+
+            Self::Set(object, name, value) => {
+                let object = object.eval(state)?;
+                let value = value.eval(state)?;
+
+                class::set_property(&object, name, value.clone(), line)?;
+                Ok(value)
+            }
+
+            Self::Super(name) => class::get_super(name, state, line),
+
+            Self::Yield(_) => unreachable!("yields are run by Generator"),
+
+            // The parser only allows `this` in methods, which have it bound.
+            Self::This => Ok(state.get(&"this".to_string()).unwrap()),
+
+            Self::Identifier(id) => state
+                .get(id)
+                .ok_or_else(|| ErrorState::runtime_error(format!("undefined variable {id}"), line)),
             Self::StringLiteral(s) => Ok(Value::String(s.clone())),
             Self::NumberLiteral(n) => Ok(Value::Number(*n)),
+            Self::IntegerLiteral(n) => Ok(Value::Int(*n)),
+            Self::BigIntegerLiteral(n) => Ok(Value::BigInt(n.clone())),
             Self::True => Ok(Value::Boolean(true)),
             Self::False => Ok(Value::Boolean(false)),
             Self::Nil => Ok(Value::Nil),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::cmp::Ordering;
+    use std::rc::Rc;
+
+    use super::{
+        big_float_cmp, format_number, get_index, int_float_cmp, power, shift, Value,
+        MAX_RESULT_BITS,
+    };
+    use crate::bigint::BigInt;
+    use crate::map::{Map, MapKey};
+
+    #[test]
+    fn int_float_comparison() {
+        // 2^53 + 1 has no exact float, so it mustn't round to 2^53
+        let n = 9007199254740993;
+        assert_ne!(Value::Int(n), Value::Number(9007199254740992.0));
+        assert_eq!(
+            int_float_cmp(n, 9007199254740992.0),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(Value::Int(n - 1), Value::Number(9007199254740992.0));
+
+        assert_eq!(int_float_cmp(1, 1.5), Some(Ordering::Less));
+        assert_eq!(int_float_cmp(-1, -1.5), Some(Ordering::Greater));
+        assert_eq!(int_float_cmp(-2, -1.5), Some(Ordering::Less));
+        assert_eq!(int_float_cmp(0, -0.0), Some(Ordering::Equal));
+        assert_eq!(
+            int_float_cmp(i64::MAX, 9223372036854775808.0),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            int_float_cmp(i64::MIN, -9223372036854775808.0),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            int_float_cmp(i64::MIN, f64::NEG_INFINITY),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(int_float_cmp(0, f64::NAN), None);
+    }
+
+    #[test]
+    fn number_formatting() {
+        assert_eq!(format_number(1.5), "1.5");
+        assert_eq!(format_number(-0.0), "-0");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_number(1e21), "1e+21");
+        assert_eq!(format_number(-1.5e300), "-1.5e+300");
+        assert_eq!(format_number(0.000001), "0.000001");
+        assert_eq!(format_number(1.5e-7), "1.5e-7");
+        assert_eq!(format_number(5e-324), "5e-324");
+    }
+
+    #[test]
+    fn bigint_float_comparison() {
+        // 2^64 + 1 rounds to 2^64 as a float
+        let n = BigInt::parse_radix("18446744073709551617", 10).unwrap();
+        assert_ne!(
+            Value::BigInt(n.clone()),
+            Value::Number(18446744073709551616.0)
+        );
+        assert_eq!(
+            big_float_cmp(&n, 18446744073709551616.0),
+            Some(Ordering::Greater)
+        );
+
+        let n = BigInt::parse_radix("100000000000000000000", 10).unwrap();
+        assert_eq!(Value::BigInt(n.clone()), Value::Number(1e20));
+        assert_eq!(
+            big_float_cmp(&n.neg(), -1e20 - 16384.0),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(big_float_cmp(&n, f64::INFINITY), Some(Ordering::Less));
+        assert_eq!(big_float_cmp(&n, f64::NAN), None);
+    }
+
+    #[test]
+    fn string_indexing() {
+        // "e" followed by a combining acute accent is a single grapheme
+        let s = Value::String("cafe\u{301}!".to_string());
+        let at = |i| get_index(&s, &Value::Int(i), 0).unwrap();
+        assert_eq!(at(3), Value::String("e\u{301}".to_string()));
+        assert_eq!(at(4), Value::String("!".to_string()));
+        assert_eq!(at(-2), Value::String("e\u{301}".to_string()));
+        assert!(get_index(&s, &Value::Int(5), 0).is_err());
+    }
+
+    #[test]
+    fn display_cycles() {
+        let inner = Value::List(Rc::new(RefCell::new(vec![Value::Int(2)])));
+        let xs = Rc::new(RefCell::new(vec![Value::Int(1), inner.clone(), inner]));
+        xs.borrow_mut().push(Value::List(xs.clone()));
+        // a list shared but not nested inside itself is written out each time
+        assert_eq!(Value::List(xs.clone()).to_string(), "[1, [2], [2], [...]]");
+        // break the cycle so the test doesn't leak
+        xs.borrow_mut().clear();
+
+        let m = Rc::new(RefCell::new(Map::new()));
+        let key = MapKey::String("self".to_string());
+        m.borrow_mut().insert(key.clone(), Value::Map(m.clone()));
+        assert_eq!(Value::Map(m.clone()).to_string(), "{self: {...}}");
+        m.borrow_mut().remove(&key);
+    }
+
+    #[test]
+    fn power_size_limit() {
+        let pow = |a, b| power(&Value::Int(a), &Value::Int(b), 0);
+
+        // the largest power of two allowed has MAX_RESULT_BITS bits
+        let Ok(Value::BigInt(n)) = pow(2, MAX_RESULT_BITS as i64 - 1) else {
+            panic!("expected a BigInt");
+        };
+        assert_eq!(n.bit_len(), MAX_RESULT_BITS);
+
+        assert!(pow(2, MAX_RESULT_BITS as i64).is_err());
+        assert!(pow(3, 4000000000).is_err());
+        assert!(pow(1 << 40, 1 << 20).is_err());
+
+        // unless the base is 0, 1 or -1
+        assert_eq!(pow(0, 100000000000).unwrap(), Value::Int(0));
+        assert_eq!(pow(1, 100000000000).unwrap(), Value::Int(1));
+        assert_eq!(pow(-1, 100000000000).unwrap(), Value::Int(1));
+        assert_eq!(pow(-1, 100000000001).unwrap(), Value::Int(-1));
+        assert_eq!(pow(0, 0).unwrap(), Value::Int(1));
+    }
+
+    #[test]
+    fn shift_size_limit() {
+        let shl = |a, n| shift(&Value::Int(a), &Value::Int(n), 0, true);
+
+        let Ok(Value::BigInt(n)) = shl(1, MAX_RESULT_BITS as i64 - 1) else {
+            panic!("expected a BigInt");
+        };
+        assert_eq!(n.bit_len(), MAX_RESULT_BITS);
+
+        assert!(shl(1, MAX_RESULT_BITS as i64).is_err());
+        assert!(shl(1, 4000000000).is_err());
+        assert_eq!(shl(0, 4000000000).unwrap(), Value::Int(0));
+    }
+}
@@ -1,81 +1,957 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::class::{Class, Instance, Trait};
 use crate::config::Config;
-use crate::error::ErrorState;
+use crate::error::{Error, ErrorState};
 use crate::eval::Value;
-use crate::expr::{Decl, ExprData, Program, Stmt};
+use crate::expr::{Decl, Expr, ExprData, MatchArm, MethodKind, Pattern, Program, Stmt};
+use crate::generator::Generator;
+use crate::map::{Map, MapKey};
+use crate::module::Modules;
+use crate::native::NATIVES;
+use crate::resolver::Resolver;
+
+/// A single level of variable bindings. Scopes are shared so that closures can keep the scope they
+/// were created in alive.
+#[derive(Default)]
+pub struct Scope {
+    vars: HashMap<String, Value>,
+    // the vars that were declared const
+    consts: HashSet<String>,
+    parent: Option<Rc<RefCell<Scope>>>,
+}
 
+impl Scope {
+    pub fn new_child(parent: Rc<RefCell<Scope>>) -> Rc<RefCell<Scope>> {
+        Rc::new(RefCell::new(Self {
+            vars: HashMap::new(),
+            consts: HashSet::new(),
+            parent: Some(parent),
+        }))
+    }
+}
+
+/// The innermost scope that code is currently executing in.
 pub struct Environment {
-    globals: HashMap<String, Value>,
+    scope: Rc<RefCell<Scope>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
+        let mut globals = Scope::default();
+        for native in NATIVES {
+            globals
+                .vars
+                .insert(native.name.to_string(), Value::Native(native));
+        }
+
         Self {
-            globals: HashMap::new(),
+            scope: Rc::new(RefCell::new(globals)),
         }
     }
 
+    /// An environment for running code in the given scope, e.g. the body of a function.
+    pub fn with_scope(scope: Rc<RefCell<Scope>>) -> Self {
+        Self { scope }
+    }
+
+    pub fn scope(&self) -> Rc<RefCell<Scope>> {
+        self.scope.clone()
+    }
+
+    /// Defines (or redefines) a variable in the innermost scope.
     pub fn insert(&mut self, k: String, v: Value) {
-        self.globals.insert(k, v);
+        let mut scope = self.scope.borrow_mut();
+        scope.consts.remove(&k);
+        scope.vars.insert(k, v);
+    }
+
+    /// Defines a const in the innermost scope.
+    pub fn insert_const(&mut self, k: String, v: Value) {
+        let mut scope = self.scope.borrow_mut();
+        scope.consts.insert(k.clone());
+        scope.vars.insert(k, v);
+    }
+
+    /// Updates an existing variable in the innermost scope that defines it. Fails if the variable
+    /// isn't defined or is a const.
+    ///
+    /// The resolver catches most assignments to consts before the program runs, but a function can
+    /// be declared before the const it assigns to.
+    pub fn assign(&mut self, k: &String, v: Value, line: u32) -> Result<(), ErrorState> {
+        let mut scope = self.scope.clone();
+
+        loop {
+            let mut current = scope.borrow_mut();
+            if current.consts.contains(k) {
+                return Err(ErrorState::runtime_error(
+                    format!("can't assign to const {k}"),
+                    line,
+                ));
+            }
+            if let Some(slot) = current.vars.get_mut(k) {
+                *slot = v;
+                return Ok(());
+            }
+
+            let parent = current.parent.clone().ok_or_else(|| {
+                ErrorState::runtime_error(format!("undefined variable {k}"), line)
+            })?;
+            drop(current);
+            scope = parent;
+        }
     }
 
     pub fn get(&mut self, k: &String) -> Option<Value> {
-        Some(self.globals[k].clone())
+        let mut scope = self.scope.clone();
+
+        loop {
+            if let Some(v) = scope.borrow().vars.get(k) {
+                return Some(v.clone());
+            }
+
+            let parent = scope.borrow().parent.clone()?;
+            scope = parent;
+        }
     }
+
+    // Runs f in a new scope nested in the current one, restoring the current scope afterwards even
+    // if f fails.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.scope.clone();
+        self.scope = Scope::new_child(outer.clone());
+
+        let result = f(self);
+
+        self.scope = outer;
+        result
+    }
+}
+
+/// How a statement finished executing, for statements that can jump out of the code around them.
+pub enum Flow {
+    Normal,
+    Return(Value),
+    Break,
+    Continue,
+}
+
+impl Decl {
+    pub fn exec(&self, env: &mut Environment) -> Result<Flow, ErrorState> {
+        match self {
+            Decl::VarDecl(id, expr) | Decl::ConstDecl(id, expr) => {
+                let val = expr.eval(env)?;
+
+                match &id.data {
+                    ExprData::Identifier(s) if matches!(self, Decl::ConstDecl(..)) => {
+                        env.insert_const(s.clone(), val);
+                    }
+                    ExprData::Identifier(s) => {
+                        env.insert(s.clone(), val);
+                    }
+                    _ => {
+                        panic!("expected identifier");
+                    }
+                }
+
+                Ok(Flow::Normal)
+            }
+            Decl::Class(decl) => {
+                let class = Class::declare(decl, env)?;
+                env.insert(decl.name.clone(), class);
+
+                Ok(Flow::Normal)
+            }
+            Decl::Trait(decl) => {
+                env.insert(decl.name.clone(), Trait::declare(decl, env));
+
+                Ok(Flow::Normal)
+            }
+            Decl::Stmt(stmt) => stmt.exec(env),
+            Decl::Export(decl) => decl.exec(env),
+            // The parser only allows imports at the top level, where Modules runs them.
+            Decl::Import(..) => unreachable!("imports are executed by Modules"),
+        }
+    }
+}
+
+impl Stmt {
+    pub fn exec(&self, env: &mut Environment) -> Result<Flow, ErrorState> {
+        match self {
+            Stmt::Expr(e) => {
+                e.eval(env)?;
+            }
+            Stmt::Print(e) => {
+                let v = e.eval(env)?;
+                println!("{v}");
+            }
+            Stmt::Block(decls) => return env.nested(|env| exec_block(decls, env)),
+            Stmt::Return(e) => {
+                let v = match e {
+                    Some(e) => e.eval(env)?,
+                    None => Value::Nil,
+                };
+
+                return Ok(Flow::Return(v));
+            }
+            Stmt::If(cond, then_branch, else_branch) => {
+                if cond.eval(env)?.is_truthy() {
+                    return then_branch.exec(env);
+                } else if let Some(else_branch) = else_branch {
+                    return else_branch.exec(env);
+                }
+            }
+            Stmt::While(cond, body) => {
+                while cond.eval(env)?.is_truthy() {
+                    match body.exec(env)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => (),
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                }
+            }
+            Stmt::For(init, cond, incr, body) => {
+                return env.nested(|env| exec_for(init, cond, incr, body, env))
+            }
+            Stmt::ForIn(name, iterable, body) => {
+                let v = iterable.eval(env)?;
+                return exec_for_in(name, &v, body, iterable.line, env);
+            }
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Throw(e) => {
+                let v = e.eval(env)?;
+                return Err(ErrorState::thrown(v, e.line));
+            }
+            Stmt::Try(body, catch, finally) => return exec_try(body, catch, finally, env),
+            Stmt::Match(subject, arms) => {
+                let v = subject.eval(env)?;
+                return exec_match(&v, arms, env);
+            } // The statements of a generator that contain a yield are run by the generator itself.
+        }
+
+        Ok(Flow::Normal)
+    }
+}
+
+// The loop variable is scoped to the loop. Unlike a while loop, `continue` still runs the
+// increment.
+fn exec_for(
+    init: &Option<Box<Decl>>,
+    cond: &Option<Expr>,
+    incr: &Option<Expr>,
+    body: &Stmt,
+    env: &mut Environment,
+) -> Result<Flow, ErrorState> {
+    if let Some(init) = init {
+        init.exec(env)?;
+    }
+
+    loop {
+        if let Some(cond) = cond {
+            if !cond.eval(env)?.is_truthy() {
+                break;
+            }
+        }
+
+        match body.exec(env)? {
+            Flow::Break => break,
+            Flow::Normal | Flow::Continue => (),
+            flow @ Flow::Return(_) => return Ok(flow),
+        }
+
+        if let Some(incr) = incr {
+            incr.eval(env)?;
+        }
+    }
+
+    Ok(Flow::Normal)
+}
+
+// Each iteration gets its own scope, so closures in the body capture that iteration's value.
+fn exec_for_in(
+    name: &str,
+    iterable: &Value,
+    body: &Stmt,
+    line: u32,
+    env: &mut Environment,
+) -> Result<Flow, ErrorState> {
+    for v in Values::new(iterable, line)? {
+        let v = v?;
+        let flow = env.nested(|env| {
+            env.insert(name.to_string(), v);
+            body.exec(env)
+        })?;
+
+        match flow {
+            Flow::Break => break,
+            Flow::Normal | Flow::Continue => (),
+            flow @ Flow::Return(_) => return Ok(flow),
+        }
+    }
+
+    Ok(Flow::Normal)
+}
+
+/// The values a for-in loop visits, produced one at a time.
+///
+/// Lists are read an element at a time, so the loop sees elements that the body appends. Maps
+/// iterate over their keys as they were when the loop started. Generators are resumed for each
+/// value, which can fail, and the loop ends when they finish.
+///
+/// Instances can be iterated over too. If the class has an `iter()` method, the loop goes over
+/// what it returns. An instance with a `next()` method is an iterator, which the loop calls for
+/// each value until it returns nil.
+pub enum Values {
+    // the list, and the index of the next element
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    // the keys of a map or the graphemes of a string, taken when the loop starts
+    Taken(std::vec::IntoIter<Value>),
+    Range(std::ops::Range<i64>),
+    // the generator, and the line of the loop
+    Generator(Rc<Generator>, u32),
+    // an instance with a next() method, and the line of the loop
+    Iterator(Rc<Instance>, u32),
+}
+
+impl Values {
+    pub fn new(iterable: &Value, line: u32) -> Result<Self, ErrorState> {
+        let values = match iterable {
+            Value::List(l) => Self::List(l.clone(), 0),
+            Value::Map(m) => {
+                let keys = m
+                    .borrow()
+                    .iter()
+                    .map(|(k, _)| k.to_value())
+                    .collect::<Vec<_>>();
+                Self::Taken(keys.into_iter())
+            }
+            Value::String(s) => {
+                let graphemes = s
+                    .graphemes(true)
+                    .map(|g| Value::String(g.to_string()))
+                    .collect::<Vec<_>>();
+                Self::Taken(graphemes.into_iter())
+            }
+            Value::Range(start, end) => Self::Range(*start..*end),
+            Value::Generator(g) => Self::Generator(g.clone(), line),
+            Value::Instance(instance) => match instance.call_method("iter", line) {
+                Some(iterable) => match iterable? {
+                    // don't call iter() again on what it returned
+                    Value::Instance(iterator) => Self::iterator(iterator, line)?,
+                    iterable => Self::new(&iterable, line)?,
+                },
+                None => Self::iterator(instance.clone(), line)?,
+            },
+            v => {
+                return Err(ErrorState::runtime_error(
+                    format!(
+                        "can only iterate over lists, maps, strings, ranges, generators and \
+                         instances, got {v}"
+                    ),
+                    line,
+                ))
+            }
+        };
+
+        Ok(values)
+    }
+
+    // Iterates by calling the instance's next() method.
+    fn iterator(instance: Rc<Instance>, line: u32) -> Result<Self, ErrorState> {
+        if instance.class.find(MethodKind::Method, "next").is_none() {
+            return Err(ErrorState::runtime_error(
+                format!(
+                    "can only iterate over instances with an iter() or next() method, got {}",
+                    Value::Instance(instance)
+                ),
+                line,
+            ));
+        }
+
+        Ok(Self::Iterator(instance, line))
+    }
+}
+
+impl Iterator for Values {
+    type Item = Result<Value, ErrorState>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::List(l, i) => {
+                let v = l.borrow().get(*i).cloned();
+                *i += 1;
+                v.map(Ok)
+            }
+            Self::Taken(values) => values.next().map(Ok),
+            Self::Range(range) => range.next().map(|n| Ok(Value::Int(n))),
+            Self::Generator(g, line) => g.next(*line).transpose(),
+            Self::Iterator(instance, line) => match instance.call_method("next", *line)? {
+                Ok(Value::Nil) => None,
+                v => Some(v),
+            },
+        }
+    }
+}
+
+fn exec_match(v: &Value, arms: &[MatchArm], env: &mut Environment) -> Result<Flow, ErrorState> {
+    for arm in arms {
+        let mut bindings = vec![];
+        if !arm.pattern.matches(v, env, arm.line, &mut bindings)? {
+            continue;
+        }
+
+        // Bindings are scoped to the arm. None means the guard failed.
+        let flow = env.nested(|env| {
+            for (name, v) in bindings {
+                env.insert(name, v);
+            }
+
+            if let Some(guard) = &arm.guard {
+                if !guard.eval(env)?.is_truthy() {
+                    return Ok(None);
+                }
+            }
+
+            arm.body.exec(env).map(Some)
+        })?;
+
+        if let Some(flow) = flow {
+            return Ok(flow);
+        }
+    }
+
+    Ok(Flow::Normal)
+}
+
+impl Pattern {
+    // On success, bindings holds the names bound by the pattern. Class names in instance patterns
+    // are looked up in env, which fails if they aren't classes.
+    fn matches(
+        &self,
+        v: &Value,
+        env: &mut Environment,
+        line: u32,
+        bindings: &mut Vec<(String, Value)>,
+    ) -> Result<bool, ErrorState> {
+        Ok(match self {
+            Pattern::Wildcard => true,
+            Pattern::Binding(name) => {
+                bindings.push((name.clone(), v.clone()));
+                true
+            }
+            Pattern::Literal(lit) => lit == v,
+            Pattern::List(patterns) => match v {
+                Value::List(l) => {
+                    let l = l.borrow();
+                    if l.len() != patterns.len() {
+                        return Ok(false);
+                    }
+                    for (p, v) in patterns.iter().zip(l.iter()) {
+                        if !p.matches(v, env, line, bindings)? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                _ => false,
+            },
+            Pattern::Or(alternatives) => {
+                let start = bindings.len();
+                for p in alternatives {
+                    // drop anything bound by a partial match of an earlier alternative
+                    bindings.truncate(start);
+                    if p.matches(v, env, line, bindings)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Pattern::Instance(name, fields) => {
+                let class = match env.get(name) {
+                    Some(Value::Class(class)) => class,
+                    Some(v) => {
+                        return Err(ErrorState::runtime_error(
+                            format!("{name} in pattern must be a class, got {v}"),
+                            line,
+                        ))
+                    }
+                    None => {
+                        return Err(ErrorState::runtime_error(
+                            format!("undefined variable {name}"),
+                            line,
+                        ))
+                    }
+                };
+                let Value::Instance(instance) = v else {
+                    return Ok(false);
+                };
+                if !instance.class.is_subclass_of(&class) {
+                    return Ok(false);
+                }
+
+                for (field, p) in fields {
+                    match instance.field(field) {
+                        Some(v) if p.matches(&v, env, line, bindings)? => (),
+                        _ => return Ok(false),
+                    }
+                }
+                true
+            }
+        })
+    }
+}
+
+// The finally block runs however the body and handler finished. If it jumps or errors itself, that
+// replaces their outcome.
+fn exec_try(
+    body: &[Decl],
+    catch: &Option<(String, Vec<Decl>)>,
+    finally: &Option<Vec<Decl>>,
+    env: &mut Environment,
+) -> Result<Flow, ErrorState> {
+    let mut result = env.nested(|env| exec_block(body, env));
+
+    if let Some((name, handler)) = catch {
+        result = match result {
+            Err(e @ (ErrorState::RuntimeErr(..) | ErrorState::Thrown(..))) => env.nested(|env| {
+                env.insert(name.clone(), caught_value(e));
+                exec_block(handler, env)
+            }),
+            result => result,
+        };
+    }
+
+    if let Some(finally) = finally {
+        match env.nested(|env| exec_block(finally, env))? {
+            Flow::Normal => (),
+            flow => return Ok(flow),
+        }
+    }
+
+    result
+}
+
+// Thrown values are caught as they are. Runtime errors are caught as a map with their message,
+// line and stack (the lines of the calls between the error and the try, innermost first).
+fn caught_value(e: ErrorState) -> Value {
+    match e {
+        ErrorState::Thrown(v, _, _) => v,
+        ErrorState::RuntimeErr(e, stack) => {
+            let mut map = Map::new();
+            let mut set = |k: &str, v| map.insert(MapKey::String(k.to_string()), v);
+
+            set("message", Value::String(e.message().to_string()));
+            set("line", Value::Int(e.line() as i64));
+            set(
+                "stack",
+                Value::List(Rc::new(RefCell::new(
+                    stack.into_iter().map(|l| Value::Int(l as i64)).collect(),
+                ))),
+            );
+
+            Value::Map(Rc::new(RefCell::new(map)))
+        }
+        ErrorState::ScanErrs(_) | ErrorState::ParseErrs(_) | ErrorState::ResolveErrs(_) => {
+            unreachable!("only runtime errors can be caught")
+        }
+    }
+}
+
+/// Executes declarations in order, stopping early if one of them jumps.
+pub fn exec_block(decls: &[Decl], env: &mut Environment) -> Result<Flow, ErrorState> {
+    for decl in decls {
+        match decl.exec(env)? {
+            Flow::Normal => (),
+            flow => return Ok(flow),
+        }
+    }
+
+    Ok(Flow::Normal)
 }
 
 pub struct ExecState {
     config: Config,
     env: Environment,
+    resolver: Resolver,
+    modules: Modules,
+    // the directory imports are relative to: the main file's, or the working directory in the REPL
+    dir: PathBuf,
 }
 
 impl ExecState {
     pub fn new(config: Config) -> Self {
+        let main = config.file.as_ref().map(Path::new);
+        let dir = match main.and_then(Path::parent) {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
         Self {
+            modules: Modules::new(main),
+            dir,
             config,
             env: Environment::new(),
+            resolver: Resolver::new(),
         }
     }
 
+    /// Checks the program before it's executed, returning its warnings. Globals declared by earlier
+    /// programs (i.e. earlier lines in the REPL) are taken into account once they have run.
+    pub fn resolve(&mut self, program: &Program) -> Result<Vec<Error>, ErrorState> {
+        self.resolver.resolve(program)
+    }
+
     pub fn exec(&mut self, program: Program) -> Result<(), ErrorState> {
         for decl in program {
             if self.config.debug_ast {
                 decl.pretty();
             }
 
-            match decl {
-                Decl::VarDecl(id, expr) => {
-                    let val = expr.eval(&mut self.env)?;
-
-                    match id.data {
-                        ExprData::Identifier(s) => {
-                            self.env.insert(s, val);
-                        }
-                        _ => {
-                            panic!("expected identifier");
-                        }
-                    }
-                }
-                Decl::Stmt(stmt) => match stmt {
-                    Stmt::Expr(e) => {
-                        let val = e.eval(&mut self.env);
-                        match val {
-                            Ok(_v) => (),
-                            Err(e) => println!("{e}"),
-                        }
-                    }
-                    Stmt::Print(e) => {
-                        let val = e.eval(&mut self.env);
-                        match val {
-                            Ok(v) => println!("{v}"),
-                            Err(e) => println!("{e}"),
-                        }
-                    }
-                },
+            match self.modules.exec_decl(&decl, &mut self.env, &self.dir) {
+                Ok(_) => self.resolver.declared(&decl),
+                // A failed declaration stops the program, since later code will expect the name to
+                // be defined, but other top-level statements just report their error.
+                Err(e) if !matches!(decl, Decl::Stmt(..)) => return Err(e),
+                Err(e) => println!("{e}"),
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{global, run};
+
+    #[test]
+    fn update_evaluates_target_once() {
+        let mut env = run("
+            var calls = 0;
+            var i = fun () { calls += 1; return 1; };
+            var xs = [1, 2, 3];
+            xs[i()] += 5;
+            xs[i()]++;
+        ");
+        assert_eq!(global(&mut env, "calls"), "2");
+        assert_eq!(global(&mut env, "xs"), "[1, 8, 3]");
+    }
+
+    #[test]
+    fn catching_runtime_errors() {
+        let mut env = run("
+            var f = fun () {
+                return nil + 1;
+            };
+            var e = nil;
+            try { f(); } catch (err) { e = err; }
+            var thrown = nil;
+            try { throw [1]; } catch (v) { thrown = v; }
+        ");
+        // lines count from 0, and the source starts with a newline
+        assert_eq!(
+            global(&mut env, "e"),
+            "{message: can only add numbers or strings, line: 2, stack: [5]}"
+        );
+        assert_eq!(global(&mut env, "thrown"), "[1]");
+    }
+
+    #[test]
+    fn finally_runs_after_return() {
+        let mut env = run("
+            var log = [];
+            var f = fun () {
+                try { return 1; } finally { push(log, \"finally\"); }
+            };
+            var result = f();
+        ");
+        assert_eq!(global(&mut env, "result"), "1");
+        assert_eq!(global(&mut env, "log"), "[finally]");
+    }
+
+    #[test]
+    fn finally_replaces_outcome() {
+        let mut env = run("
+            var returned = (fun () { try { return 1; } finally { return 2; } })();
+            var thrown = (fun () { try { throw 1; } finally { return 3; } })();
+            var caught = (fun () {
+                try { throw 1; } catch (e) { return 4; } finally { return 5; }
+            })();
+            var i = 0;
+            while (true) {
+                i += 1;
+                try { throw 1; } finally { break; }
+            }
+        ");
+        assert_eq!(global(&mut env, "returned"), "2");
+        assert_eq!(global(&mut env, "thrown"), "3");
+        assert_eq!(global(&mut env, "caught"), "5");
+        assert_eq!(global(&mut env, "i"), "1");
+    }
+
+    #[test]
+    fn classes() {
+        let mut env = run("
+            class Counter {
+                init(start) { this.count = start; }
+                add(n) { this.count += n; return this; }
+                doubled { return this.count * 2; }
+                set doubled(v) { this.count = v / 2; }
+                class zero() { return this(0); }
+            }
+            var c = Counter(1).add(2);
+            var count = c.count;
+            var doubled = c.doubled;
+            c.doubled = 10;
+            var halved = c.count;
+            var zero = Counter.zero().count;
+            var add = c.add;
+            add(1);
+            var bound = c.count;
+            var old = c.count++;
+            c.doubled *= 2;
+            var after = c.count;
+        ");
+        assert_eq!(global(&mut env, "count"), "3");
+        assert_eq!(global(&mut env, "doubled"), "6");
+        assert_eq!(global(&mut env, "halved"), "5");
+        assert_eq!(global(&mut env, "zero"), "0");
+        assert_eq!(global(&mut env, "bound"), "6");
+        assert_eq!(global(&mut env, "old"), "6");
+        // `c.doubled *= 2` goes through the getter and then the setter
+        assert_eq!(global(&mut env, "after"), "14");
+        // the object expression is evaluated once
+        let mut env = run("
+            class P { init() { this.calls = 0; } }
+            var p = P();
+            var get = fun () { p.calls++; return p; };
+            get().x = 1;
+            get().x += 5;
+            var x = p.x;
+            var calls = p.calls;
+        ");
+        assert_eq!(global(&mut env, "x"), "6");
+        assert_eq!(global(&mut env, "calls"), "2");
+    }
+
+    #[test]
+    fn inheritance() {
+        let mut env = run("
+            class A {
+                init(x) { this.x = x; }
+                name() { return \"A\"; }
+                label { return \"a\" + this.name(); }
+                class make() { return \"A.make\"; }
+            }
+            class B < A {
+                init(x) { super.init(x + 1); }
+                name() { return \"B\" + super.name(); }
+                class make() { return \"B>\" + super.make(); }
+            }
+            var b = B(1);
+            var x = b.x;
+            var label = b.label;
+            var made = B.make();
+        ");
+        assert_eq!(global(&mut env, "x"), "2");
+        assert_eq!(global(&mut env, "label"), "aBA");
+        assert_eq!(global(&mut env, "made"), "B>A.make");
+    }
+
+    #[test]
+    fn traits() {
+        let mut env = run("
+            trait Named {
+                name() { return \"named\"; }
+                greeting { return \"hi \" + this.name(); }
+            }
+            trait Sized {
+                size() { return this.n; }
+                name() { return \"sized\"; }
+            }
+            class Base {
+                size() { return 0; }
+                kind() { return \"base\"; }
+            }
+            class Box < Base with Named, Sized {
+                init(n) { this.n = n; }
+                name() { return \"box\"; }
+            }
+            var b = Box(3);
+            var greeting = b.greeting;
+            var size = b.size();
+            var kind = b.kind();
+
+            var error = nil;
+            try {
+                class Clash with Named, Sized {}
+            } catch (e) {
+                error = e[\"message\"];
+            }
+        ");
+        // the class's own name() settles the clash between Named and Sized
+        assert_eq!(global(&mut env, "greeting"), "hi box");
+        assert_eq!(global(&mut env, "size"), "3");
+        assert_eq!(global(&mut env, "kind"), "base");
+        assert_eq!(
+            global(&mut env, "error"),
+            "traits Named and Sized both define name for class Clash"
+        );
+    }
+
+    #[test]
+    fn instance_patterns() {
+        let mut env = run("
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+                sum { return this.x + this.y; }
+            }
+            class Point3 < Point {}
+            class Other { init() { this.x = 0; } }
+            var describe = fun (p) {
+                match p {
+                    Point { x: 0, y } => return \"on the y axis at ${y}\";
+                    Point { x, y: [z] } => return \"nested ${x} ${z}\";
+                    Point { sum } => return \"getter\";
+                    Point {} => return \"point\";
+                    _ => return \"other\";
+                }
+            };
+            var axis = describe(Point3(0, 2));
+            var nested = describe(Point(1, [2]));
+            var point = describe(Point(1, 2));
+            var other = describe(Other());
+
+            var error = nil;
+            try {
+                match 1 { describe { x } => {} }
+            } catch (e) {
+                error = e[\"message\"];
+            }
+        ");
+        assert_eq!(global(&mut env, "axis"), "on the y axis at 2");
+        assert_eq!(global(&mut env, "nested"), "nested 1 2");
+        // patterns only look at fields, not getters
+        assert_eq!(global(&mut env, "point"), "point");
+        assert_eq!(global(&mut env, "other"), "other");
+        assert_eq!(
+            global(&mut env, "error"),
+            "describe in pattern must be a class, got <fn>"
+        );
+    }
+
+    #[test]
+    fn for_in_lists() {
+        // elements appended during the loop are visited
+        let mut env = run("
+            var xs = [1, 2];
+            var seen = [];
+            for (x in xs) {
+                push(seen, x);
+                if (x < 3) push(xs, x + 2);
+            }
+        ");
+        assert_eq!(global(&mut env, "seen"), "[1, 2, 3, 4]");
+
+        // each iteration has its own binding for closures to capture
+        let mut env = run("
+            var fs = [];
+            for (i in 0..3) push(fs, () => i);
+            var got = [fs[0](), fs[1](), fs[2]()];
+        ");
+        assert_eq!(global(&mut env, "got"), "[0, 1, 2]");
+    }
+
+    #[test]
+    fn for_in_maps_and_strings() {
+        // the keys are taken before the loop starts
+        let mut env = run("
+            var m = {\"a\": 1, \"b\": 2};
+            var keys = [];
+            for (k in m) {
+                push(keys, k);
+                m[k + \"!\"] = 0;
+            }
+        ");
+        assert_eq!(global(&mut env, "keys"), "[a, b]");
+        assert_eq!(global(&mut env, "m"), "{a: 1, b: 2, a!: 0, b!: 0}");
+
+        // strings by grapheme cluster: e and a combining accent are one
+        let mut env = run("
+            var chars = [];
+            for (c in \"cafe\\u{301}!\") push(chars, c);
+        ");
+        assert_eq!(global(&mut env, "chars"), "[c, a, f, e\u{301}, !]");
+    }
+
+    #[test]
+    fn for_in_instances() {
+        let mut env = run("
+            class Countdown {
+                init(n) { this.n = n; }
+                next() {
+                    if (this.n == 0) return nil;
+                    this.n -= 1;
+                    return this.n + 1;
+                }
+            }
+            class Bag {
+                init(items) { this.items = items; }
+                iter() { return this.items; }
+            }
+            class Timer {
+                iter() { return Countdown(2); }
+            }
+            var seen = [];
+            for (n in Countdown(3)) push(seen, n);
+            for (x in Bag([\"a\", \"b\"])) push(seen, x);
+            for (n in Timer()) push(seen, n);
+
+            var error = nil;
+            try {
+                for (x in Bag(1)) {}
+            } catch (e) {
+                error = e[\"message\"];
+            }
+        ");
+        assert_eq!(global(&mut env, "seen"), "[3, 2, 1, a, b, 2, 1]");
+        assert_eq!(
+            global(&mut env, "error"),
+            "can only iterate over lists, maps, strings, ranges, generators and instances, got 1"
+        );
+    }
+
+    #[test]
+    fn for_in_empty_ranges() {
+        let mut env = run("
+            var count = 0;
+            for (i in 0..0) count += 1;
+            for (i in 3..1) count += 1;
+            for (i in -1..-2) count += 1;
+        ");
+        assert_eq!(global(&mut env, "count"), "0");
+    }
+
+    #[test]
+    fn consts_at_runtime() {
+        let mut env = run("
+            var f = fun () { x = 5; };
+            var g = fun () { x += 5; };
+            const x = 1;
+            var errors = [];
+            try { f(); } catch (e) { push(errors, e[\"message\"]); }
+            try { g(); } catch (e) { push(errors, e[\"message\"]); }
+        ");
+        assert_eq!(global(&mut env, "x"), "1");
+        assert_eq!(
+            global(&mut env, "errors"),
+            "[can't assign to const x, can't assign to const x]"
+        );
+    }
+}
@@ -1,32 +1,107 @@
+use std::collections::BTreeSet;
 use std::rc::Rc;
 
+use crate::bigint::BigInt;
+use crate::eval::Value;
+
 // expression     → literal
+//                | lambda
 //                | unary
 //                | binary
-//                | grouping ;
+//                | grouping
+//                | call
+//                | index
+//                | indexSet
+//                | get
+//                | set
+//                | assign
+//                | compoundAssign
+//                | update
+//                | ternary
+//                | comma ;
 //
-// literal        → NUMBER | STRING | "true" | "false" | "nil" ;
+// literal        → NUMBER | STRING | "true" | "false" | "nil" | "this" | list | map
+//                | interpolation ;
+// list           → "[" ( expression ( "," expression )* )? "]" ;
+// map            → "{" ( expression ":" expression ( "," expression ":" expression )* )? "}" ;
+// interpolation  → ( INTERPOLATION expression )+ STRING ;
+// lambda         → "fun" "*"? "(" parameters? ")" block
+//                | "(" parameters? ")" "=>" ( block | expression ) ;
+// parameters     → param ( "," param )* ( "," "..." IDENTIFIER )? | "..." IDENTIFIER ;
+// param          → IDENTIFIER ( "=" expression )? ;  (defaults come after the required params)
 // grouping       → "(" expression ")" ;
-// unary          → ( "-" | "!" ) expression ;
+// call           → expression "(" ( argument ( "," argument )* )? ")" ;
+// argument       → expression | IDENTIFIER ":" expression ;  (named arguments come last)
+// index          → expression "[" expression "]" ;
+// indexSet       → expression "[" expression "]" "=" expression ;
+// get            → expression "." IDENTIFIER | "super" "." IDENTIFIER ;
+// set            → expression "." IDENTIFIER "=" expression ;
+// assign         → IDENTIFIER "=" expression ;
+// compoundAssign → target ( "+=" | "-=" | "*=" | "/=" ) expression ;
+// update         → ( "++" | "--" ) target | target ( "++" | "--" ) ;
+// target         → IDENTIFIER | expression "[" expression "]" | expression "." IDENTIFIER ;
+// ternary        → expression "?" expression ":" expression ;
+// comma          → expression "," expression ;
+// unary          → ( "-" | "!" | "~" ) expression ;
 // binary         → expression operator expression ;
 // operator       → "==" | "!=" | "<" | "<=" | ">" | ">="
-//                | "+"  | "-"  | "*" | "/" ;
+//                | "+"  | "-"  | "*" | "/" | "%" | "~/" | "**"
+//                | "&"  | "|"  | "^" | "<<" | ">>" ;
 
 // Precedence: (lowest = highest)
 //
+// Comma (,)
+// Assignment (= += -= *= /=)
+// Ternary (?:), right-associative
 // Equality (== !=)
 // Comparison (> >= < <=)
+// Bitwise or (|)
+// Bitwise xor (^)
+// Bitwise and (&)
+// Shift (<< >>)
 // Term (- +)
-// Factor (/ *)
-// Unary (! -)
+// Factor (/ * % ~/)
+// Unary (! - ~ ++ --)
+// Power (**), right-associative
+// Postfix (++ --)
+// Call, index
 //
-// expression     → equality
+// expression     → comma
+// comma          → assignment ( "," assignment )*
+// assignment     → target ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment | ternary
+// ternary        → equality ( "?" assignment ":" assignment )?
 // equality       → comparison ( (!= | ==) comparison )*
-// comparison     → term (( "<>" etc ) term)*
+// comparison     → range (( "<>" etc ) range)*
+// range          → bitOr ( ".." bitOr )?
+// bitOr          → bitXor ( "|" bitXor )*
+// bitXor         → bitAnd ( "^" bitAnd )*
+// bitAnd         → shift ( "&" shift )*
+// shift          → term ( ( "<<" | ">>" ) term )*
 // term           → factor (( "-" | "+" ) factor)*
-// factor         → unary ( ("/" | "*") unary )*
-// unary          → ("!" | "-") unary | primary
-// primary        → literal | "(" expression ")"
+// factor         → unary ( ("/" | "*" | "%" | "~/") unary )*
+// unary          → ("!" | "-" | "~") unary | ( "++" | "--" ) unary | power
+// power          → postfix ( "**" unary )?
+// postfix        → call ( "++" | "--" )?
+// call           → primary ( "(" callArgs? ")" | "[" expression "]" | "." IDENTIFIER )*
+// callArgs       → ( assignment | IDENTIFIER ":" assignment ) ( "," callArgs )?
+// arguments      → assignment ( "," assignment )*
+// primary        → literal | lambda | "(" expression ")" | "[" arguments? "]" | "{" entries? "}"
+//                | "super" "." IDENTIFIER
+// entries        → assignment ":" assignment ( "," assignment ":" assignment )*
+//
+// Since the comma operator has the lowest precedence, it only appears in argument lists, list and
+// map literals if parenthesized, so `f(a, b)` is a call with two arguments but `f((a, b))` has one.
+// The same goes for the initializer of a var declaration and the body of an arrow function.
+//
+// A range `a..b` is the integers from a up to but not including b. It binds looser than arithmetic,
+// so `0..n + 1` includes n.
+//
+// Unlike C, the bitwise operators bind tighter than comparisons, so `x & 1 == 0` means
+// `(x & 1) == 0`.
+//
+// `**` binds tighter than a unary operator on its left, so `-2 ** 2` is `-(2 ** 2)`, but its right
+// operand is a unary expression, so `2 ** -1` parses too. Recursing into unary for the right
+// operand is also what makes it right-associative.
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Expr {
@@ -44,8 +119,35 @@ impl Expr {
 pub enum ExprData {
     Binary(BinOp, Rc<Expr>, Rc<Expr>),
     Unary(UnaryOp, Rc<Expr>),
+    // callee, positional arguments, named arguments
+    Call(Rc<Expr>, Vec<Expr>, Vec<(String, Expr)>),
+    // list[index]
+    Index(Rc<Expr>, Rc<Expr>),
+    // list[index] = value
+    IndexSet(Rc<Expr>, Rc<Expr>, Rc<Expr>),
+    Assign(String, Rc<Expr>),
+    // target op= value, where the target is an Identifier, Index or Get. `++x` is `x += 1`.
+    CompoundAssign(BinOp, Rc<Expr>, Rc<Expr>),
+    // x++ and x--: updates the target with `+ 1` or `- 1`, but produces its old value
+    PostfixUpdate(BinOp, Rc<Expr>),
+    // cond ? then : else
+    Ternary(Rc<Expr>, Rc<Expr>, Rc<Expr>),
+    ListLiteral(Vec<Expr>),
+    MapLiteral(Vec<(Expr, Expr)>),
+    Lambda(Rc<FunctionDecl>),
+    // object.name
+    Get(Rc<Expr>, String),
+    // object.name = value
+    Set(Rc<Expr>, String, Rc<Expr>),
+    // super.name, in a method of a class with a superclass
+    Super(String),
+    // `yield value`, only where the grammar above allows it
+    Yield(Option<Rc<Expr>>),
+    This,
 
-    NumberLiteral(f32),
+    NumberLiteral(f64),
+    IntegerLiteral(i64),
+    BigIntegerLiteral(BigInt),
     Identifier(String),
     StringLiteral(String),
 
@@ -54,6 +156,69 @@ pub enum ExprData {
     Nil,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionDecl {
+    // a method's name, or the variable a function was declared as, for error messages
+    pub name: Option<String>,
+    pub params: Vec<Param>,
+    // the parameter that collects any extra positional arguments into a list
+    pub rest: Option<String>,
+    pub body: FunctionBody,
+    // declared with `fun*`, so calling it returns a generator that runs the body
+    pub generator: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassDecl {
+    pub name: String,
+    // evaluated when the class is declared, and must be a class
+    pub superclass: Option<Expr>,
+    // evaluated when the class is declared, and must be traits
+    pub traits: Vec<Expr>,
+    pub methods: Vec<Method>,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraitDecl {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Method {
+    pub name: String,
+    pub kind: MethodKind,
+    pub decl: Rc<FunctionDecl>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MethodKind {
+    // called on instances; `init` is called by the class to initialize a new one
+    Method,
+    // `class name()`, called on the class itself
+    Static,
+    // `name { ... }`, called when the property is read
+    Getter,
+    // `set name(value) { ... }`, called when the property is assigned
+    Setter,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    // evaluated at call time, in the function's scope, if the argument is left out
+    pub default: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FunctionBody {
+    Block(Vec<Decl>),
+    // the concise body of an arrow function, which is returned
+    Expr(Expr),
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum BinOp {
     Eq,
@@ -67,38 +232,208 @@ pub enum BinOp {
     Sub,
     Div,
     Mult,
+    Mod,
+    // Division rounding towards negative infinity, `~/`.
+    FloorDiv,
+    Pow,
+
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    // arithmetic, i.e. sign-extending
+    Shr,
+
+    // `a..b`, the integers from a up to b, exclusive.
+    Range,
+
+    // Evaluates both sides, left first, and produces the right.
+    Comma,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum UnaryOp {
     Negative,
     Inverse,
+    BitNot,
+    // Converts any value to its printed form. There is no syntax for this; it's produced by
+    // desugaring string interpolation.
+    ToString,
 }
 
-// program        → declaration* EOF ;
+// program        → topLevelDecl* EOF ;
+//
+// topLevelDecl   → importDecl
+//                | "export" ( varDecl | constDecl | funDecl | classDecl | traitDecl )
+//                | declaration ;
+//
+// importDecl     → "import" STRING "as" IDENTIFIER ";" ;
 
 // declaration    → varDecl
+//                | constDecl
+//                | funDecl
+//                | classDecl
+//                | traitDecl
 //                | statement ;
 //
-// varDecl        → "var" IDENTIFIER ( '=' expression ) ? ;
+// varDecl        → "var" IDENTIFIER ( '=' ( expression | yield ) ) ? ;
+// constDecl      → "const" IDENTIFIER "=" ( expression | yield ) ";" ;
+// funDecl        → "fun" "*"? IDENTIFIER "(" parameters? ")" block ;  (short for a varDecl)
+// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )?
+//                  ( "with" IDENTIFIER ( "," IDENTIFIER )* )? "{" member* "}" ;
+// traitDecl      → "trait" IDENTIFIER "{" member* "}" ;
+// member         → "class"? IDENTIFIER "(" parameters? ")" block
+//                | IDENTIFIER block
+//                | "set" IDENTIFIER "(" IDENTIFIER ")" block ;
+//
+// The members of a class are its methods; `class` methods are static, called on the class rather
+// than its instances. A member without a parameter list is a getter, which runs when the property
+// is read, and a `set` member is a setter, which runs when it's assigned. Calling a class creates
+// an instance and passes the arguments to its `init` method, if it has one.
+//
+// A trait is a set of methods for classes to include with `with`. The class's own methods take
+// precedence over its traits', which take precedence over its superclass's; two traits defining
+// the same method is an error when the class is declared, unless the class defines it too.
+//
+// Consts can't be assigned to or redeclared in the same scope, which the resolver checks before
+// the program runs.
 //
 // statement      → exprStmt
-//                | printStmt ;
+//                | printStmt
+//                | returnStmt
+//                | ifStmt
+//                | whileStmt
+//                | forStmt
+//                | forInStmt
+//                | breakStmt
+//                | continueStmt
+//                | throwStmt
+//                | tryStmt
+//                | matchStmt
+//                | block ;
 //
-// exprStmt       → expression ";" ;
+// exprStmt       → ( expression | yield | IDENTIFIER "=" yield ) ";" ;
+//                  (may not start with "{", which is a block)
+// yield          → "yield" expression? ;
 // printStmt      → "print" expression ";" ;
+// returnStmt     → "return" expression? ";" ;  (only inside functions)
+// block          → "{" declaration* "}" ;
+// ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
+// whileStmt      → "while" "(" expression ")" statement ;
+// forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")"
+//                  statement ;
+// forInStmt      → "for" "(" IDENTIFIER "in" expression ")" statement ;
+// breakStmt      → "break" ";" ;  (only inside loops)
+// continueStmt   → "continue" ";" ;  (only inside loops)
+// throwStmt      → "throw" expression ";" ;
+// tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+//                  (needs a catch, a finally, or both)
+// matchStmt      → "match" expression "{" arm* "}" ;
+// arm            → pattern ( "if" expression )? "=>" statement ","? ;
+// pattern        → alternative ( "|" alternative )* ;
+// alternative    → "_" | IDENTIFIER | literal | "-" NUMBER
+//                | "[" ( pattern ( "," pattern )* )? "]"
+//                | IDENTIFIER "{" ( field ( "," field )* )? "}" ;
+// field          → IDENTIFIER ( ":" pattern )? ;
+//
+// The first arm whose pattern matches and whose guard is truthy runs, and the rest are skipped. If
+// no arm matches, nothing happens. Arm bodies are ordinary statements, so they end in `;` (or are
+// blocks); the comma after an arm is optional.
+//
+// `Point { x, y: 0 }` matches instances of the class Point or its subclasses whose fields match;
+// a bare field name binds the field to that name. Only fields are looked at, not getters, so
+// matching never runs any code.
+//
+// `yield` is only allowed in the body of a `fun*` generator, and not inside a `try` or `match`
+// there (or inside another function). Its value is what the generator is next resumed with, as in
+// `resume(g, value)`, or nil. A generator is suspended and resumed a statement at a time (see
+// generator.rs), so a yield can only make up a whole statement, a var or const initializer, or the
+// value of an assignment statement: `var x = yield 1;` works, but `print (yield 1) + 1;` doesn't.
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Stmt {
     Expr(Expr),
     Print(Expr),
+    Return(Option<Expr>),
+    Block(Vec<Decl>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    // initializer, condition, increment, body
+    For(Option<Box<Decl>>, Option<Expr>, Option<Expr>, Box<Stmt>),
+    // loop variable, iterable, body
+    ForIn(String, Expr, Box<Stmt>),
+    Break,
+    Continue,
+    Throw(Expr),
+    // body, catch clause (the name the error is bound to, and the handler), finally block
+    Try(Vec<Decl>, Option<(String, Vec<Decl>)>, Option<Vec<Decl>>),
+    Match(Expr, Vec<MatchArm>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Stmt,
+    // where the arm starts, for warnings about it
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    // `_`, which matches anything
+    Wildcard,
+    // matches anything, and binds it to the name for the guard and body
+    Binding(String),
+    // matches values that are == to it
+    Literal(Value),
+    // matches a list of the same length whose elements match
+    List(Vec<Pattern>),
+    // `a | b`, which matches if any alternative does
+    Or(Vec<Pattern>),
+    // `Name { field: pattern, ... }`, which matches instances of the named class (or a subclass)
+    // with those fields
+    Instance(String, Vec<(String, Pattern)>),
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Literal(_) | Pattern::List(_) | Pattern::Instance(..) => false,
+            Pattern::Or(alternatives) => alternatives.iter().any(Pattern::is_irrefutable),
+        }
+    }
+
+    /// The names the pattern binds when it matches.
+    pub fn bindings(&self) -> BTreeSet<&str> {
+        match self {
+            Pattern::Binding(name) => BTreeSet::from([name.as_str()]),
+            Pattern::Wildcard | Pattern::Literal(_) => BTreeSet::new(),
+            Pattern::List(patterns) | Pattern::Or(patterns) => {
+                patterns.iter().flat_map(Pattern::bindings).collect()
+            }
+            Pattern::Instance(_, fields) => fields.iter().flat_map(|(_, p)| p.bindings()).collect(),
+        }
+    }
 }
 
+// VarDecl reads better than Var next to the var keyword
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum Decl {
     // the first Expr must be an identifier
     VarDecl(Expr, Expr),
+    ConstDecl(Expr, Expr),
+    Class(Rc<ClassDecl>),
+    Trait(Rc<TraitDecl>),
     Stmt(Stmt),
+    // Imports and exports can only appear at the top level of a file.
+    // path, the name the module is bound to, line
+    Import(String, String, u32),
+    // the inner Decl must be a VarDecl, ConstDecl, Class or Trait
+    Export(Box<Decl>),
 }
 
 pub type Program = Vec<Decl>;
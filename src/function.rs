@@ -0,0 +1,317 @@
+use std::cell::{Cell, RefCell};
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::error::ErrorState;
+use crate::eval::Value;
+use crate::exec::{exec_block, Environment, Flow, Scope};
+use crate::expr::{FunctionBody, FunctionDecl};
+use crate::generator::Generator;
+
+/// How deeply Lox calls can nest before raising a "stack overflow" runtime error, instead of
+/// overflowing the Rust stack. main gives the interpreter a stack big enough for this many.
+pub const MAX_CALL_DEPTH: usize = 5_000;
+
+thread_local! {
+    // the number of Lox function calls in progress
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A function value: its declaration plus the scope it was created in.
+pub struct Function {
+    pub decl: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Scope>>,
+    // a class's `init` method, which returns the instance it initialized
+    pub initializer: bool,
+}
+
+// Not derived, since the closure may well contain the function itself.
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("params", &self.decl.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs f as a call made on the given line, failing if there are too many calls in progress.
+pub fn count_call<T>(
+    line: u32,
+    f: impl FnOnce() -> Result<T, ErrorState>,
+) -> Result<T, ErrorState> {
+    let depth = CALL_DEPTH.get();
+    if depth >= MAX_CALL_DEPTH {
+        return Err(ErrorState::runtime_error(
+            "stack overflow".to_string(),
+            line,
+        ));
+    }
+
+    CALL_DEPTH.set(depth + 1);
+    let result = f();
+    CALL_DEPTH.set(depth);
+    result
+}
+
+impl Function {
+    /// The method with `this` bound to the given instance (or class, for a static method).
+    pub fn bind(&self, this: Value) -> Function {
+        let mut env = Environment::with_scope(Scope::new_child(self.closure.clone()));
+        env.insert("this".to_string(), this);
+
+        Function {
+            decl: self.decl.clone(),
+            closure: env.scope(),
+            initializer: self.initializer,
+        }
+    }
+
+    pub fn call(&self, args: Args, line: u32) -> Result<Value, ErrorState> {
+        count_call(line, || self.call_body(args, line))
+    }
+
+    fn call_body(&self, args: Args, line: u32) -> Result<Value, ErrorState> {
+        let signature = Signature {
+            name: self.decl.name.as_deref().unwrap_or("function"),
+            params: self
+                .decl
+                .params
+                .iter()
+                .map(|p| (p.name.as_str(), p.default.is_some()))
+                .collect(),
+            variadic: self.decl.rest.is_some(),
+        };
+        let (slots, rest) = signature.bind(args, line)?;
+
+        // Defaults are evaluated in order as each parameter is bound, so they can refer to the
+        // parameters before them.
+        let mut env = Environment::with_scope(Scope::new_child(self.closure.clone()));
+        for (param, slot) in self.decl.params.iter().zip(slots) {
+            let v = match (slot, &param.default) {
+                (Some(v), _) => v,
+                (None, Some(default)) => default.eval(&mut env)?,
+                (None, None) => unreachable!("bind fills in required parameters"),
+            };
+            env.insert(param.name.clone(), v);
+        }
+        if let Some(name) = &self.decl.rest {
+            env.insert(name.clone(), Value::List(Rc::new(RefCell::new(rest))));
+        }
+
+        let v = match &self.decl.body {
+            // the body runs as the generator is resumed
+            FunctionBody::Block(decls) if self.decl.generator => {
+                return Ok(Value::Generator(Rc::new(Generator::new(decls, env))));
+            }
+            FunctionBody::Expr(e) => e.eval(&mut env)?,
+            FunctionBody::Block(decls) => match exec_block(decls, &mut env)? {
+                Flow::Return(v) => v,
+                // The parser doesn't allow break and continue to escape a function body.
+                Flow::Normal | Flow::Break | Flow::Continue => Value::Nil,
+            },
+        };
+
+        // The parser only allows a bare `return` in an initializer.
+        if self.initializer {
+            return Ok(env.get(&"this".to_string()).unwrap());
+        }
+
+        Ok(v)
+    }
+}
+
+/// The evaluated arguments of a call.
+pub struct Args {
+    pub positional: Vec<Value>,
+    pub named: Vec<(String, Value)>,
+}
+
+/// The argument for each parameter of a signature, which is None where the default should be used,
+/// and any extra positional arguments.
+pub type Bound = (Vec<Option<Value>>, Vec<Value>);
+
+/// The parameters of a function or native, for matching up the arguments of a call with them.
+pub struct Signature<'a> {
+    // used in error messages
+    pub name: &'a str,
+    // each parameter, and whether it has a default
+    pub params: Vec<(&'a str, bool)>,
+    // whether extra positional arguments are collected by a rest parameter
+    pub variadic: bool,
+}
+
+impl Signature<'_> {
+    /// Matches up the arguments with the parameters.
+    pub fn bind(&self, args: Args, line: u32) -> Result<Bound, ErrorState> {
+        let required = self.params.iter().filter(|(_, default)| !default).count();
+        let count = args.positional.len() + args.named.len();
+        if count < required || (!self.variadic && count > self.params.len()) {
+            return Err(self.error(
+                format!("expected {} but got {count}", self.arity(required)),
+                line,
+            ));
+        }
+
+        let mut slots = vec![None; self.params.len()];
+        let mut positional = args.positional.into_iter();
+        for (slot, v) in slots.iter_mut().zip(positional.by_ref()) {
+            *slot = Some(v);
+        }
+        let rest = positional.collect();
+
+        for (name, v) in args.named {
+            let i = self
+                .params
+                .iter()
+                .position(|(param, _)| *param == name)
+                .ok_or_else(|| self.error(format!("has no parameter {name}"), line))?;
+
+            if slots[i].is_some() {
+                return Err(self.error(format!("got two values for parameter {name}"), line));
+            }
+            slots[i] = Some(v);
+        }
+
+        // There were enough arguments, but a named one may have taken the place of a required one.
+        for ((param, default), slot) in self.params.iter().zip(&slots) {
+            if !default && slot.is_none() {
+                return Err(self.error(format!("is missing an argument for {param}"), line));
+            }
+        }
+
+        Ok((slots, rest))
+    }
+
+    // The accepted number of arguments, e.g. "1 argument", "2 to 3 arguments" or "at least 1
+    // argument".
+    fn arity(&self, required: usize) -> String {
+        let (count, max) = if self.variadic {
+            (format!("at least {required}"), required)
+        } else if required == self.params.len() {
+            (format!("{required}"), required)
+        } else {
+            (format!("{required} to {}", self.params.len()), usize::MAX)
+        };
+
+        if max == 1 {
+            format!("{count} argument")
+        } else {
+            format!("{count} arguments")
+        }
+    }
+
+    fn error(&self, msg: String, line: u32) -> ErrorState {
+        ErrorState::runtime_error(format!("{} {msg}", self.name), line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{Args, Signature, MAX_CALL_DEPTH};
+    use crate::eval::Value;
+    use crate::test_util::{global, run};
+
+    // f(a, b = ..., ...rest) if variadic
+    fn signature(variadic: bool) -> Signature<'static> {
+        Signature {
+            name: "f",
+            params: vec![("a", false), ("b", true)],
+            variadic,
+        }
+    }
+
+    fn args(positional: &[i64], named: &[(&str, i64)]) -> Args {
+        Args {
+            positional: positional.iter().map(|&n| Value::Int(n)).collect(),
+            named: named
+                .iter()
+                .map(|&(name, n)| (name.to_string(), Value::Int(n)))
+                .collect(),
+        }
+    }
+
+    fn bind_err(signature: &Signature, args: Args) -> String {
+        signature.bind(args, 0).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn binding() {
+        let (slots, rest) = signature(true).bind(args(&[1, 2, 3], &[]), 0).unwrap();
+        assert_eq!(slots, [Some(Value::Int(1)), Some(Value::Int(2))]);
+        assert_eq!(rest, [Value::Int(3)]);
+
+        // the default is left to the caller
+        let (slots, rest) = signature(false).bind(args(&[], &[("a", 1)]), 0).unwrap();
+        assert_eq!(slots, [Some(Value::Int(1)), None]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(bind_err(&signature(false), args(&[], &[])).contains("f expected 1 to 2 arguments"));
+        assert!(bind_err(&signature(false), args(&[1, 2, 3], &[])).contains("but got 3"));
+        assert!(bind_err(&signature(true), args(&[], &[])).contains("at least 1 argument but"));
+        assert!(bind_err(&signature(false), args(&[1], &[("c", 2)])).contains("no parameter c"));
+        assert!(bind_err(&signature(false), args(&[1], &[("a", 2)])).contains("two values for"));
+        assert!(bind_err(&signature(false), args(&[], &[("b", 2)])).contains("missing an argument"));
+    }
+
+    #[test]
+    fn named_functions() {
+        let mut env = run("
+            fun add(a, b = 1, ...rest) { return a + b + len(rest); }
+            fun* twice(x) { yield x; yield x; }
+            var sum = add(1, 2, 3, 4);
+            var pair = [];
+            for (x in twice(5)) push(pair, x);
+
+            var errors = [];
+            var id = (x) => x;
+            class C { m() {} }
+            for (f in [add, id, C().m, fun (x) {}]) {
+                try { f(1, 2, 3, 4); } catch (e) { push(errors, e[\"message\"]); }
+            }
+            try { add(); } catch (e) { push(errors, e[\"message\"]); }
+        ");
+        assert_eq!(global(&mut env, "sum"), "5");
+        assert_eq!(global(&mut env, "pair"), "[5, 5]");
+        assert_eq!(
+            global(&mut env, "errors"),
+            "[id expected 1 argument but got 4, m expected 0 arguments but got 4, \
+             function expected 1 argument but got 4, add expected at least 1 argument but got 0]"
+        );
+    }
+
+    #[test]
+    fn call_depth() {
+        let source = format!(
+            "var f = fun (n) {{ if (n == 0) return 0; return f(n - 1) + 1; }};
+            var deep = f({});
+            var caught = nil;
+            try {{ f({}); }} catch (e) {{ caught = e[\"message\"]; }}",
+            MAX_CALL_DEPTH - 1,
+            MAX_CALL_DEPTH,
+        );
+
+        // like main, on a stack with room for the deepest calls
+        let result = thread::Builder::new()
+            .stack_size(crate::STACK_SIZE)
+            .spawn(move || {
+                let mut env = run(&source);
+                ["deep", "caught"].map(|name| global(&mut env, name))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            [
+                format!("{}", MAX_CALL_DEPTH - 1),
+                "stack overflow".to_string()
+            ]
+        );
+    }
+}
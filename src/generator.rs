@@ -0,0 +1,560 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::error::ErrorState;
+use crate::eval::Value;
+use crate::exec::{Environment, Flow, Scope, Values};
+use crate::expr::{Decl, Expr, ExprData, Stmt};
+use crate::function::count_call;
+
+/// The value of calling a `fun*` function, which runs the function's body a piece at a time, up to
+/// each `yield`.
+///
+/// The rest of the interpreter keeps the state of running code on the Rust stack, where it can't be
+/// suspended. So a generator runs its body on a stack of frames of its own instead, one for each
+/// block and loop it's inside. Only the statements that contain a yield need to be run this way:
+/// since a yield can only make up a whole statement, initializer or assigned value, and isn't
+/// allowed inside a try or match, everything else (including every other expression) runs on the
+/// ordinary evaluator in one go.
+pub struct Generator {
+    state: RefCell<State>,
+}
+
+enum State {
+    Suspended(Machine),
+    // resumed, and not back at a yield yet
+    Running,
+    Done,
+}
+
+/// What a generator did when it was resumed.
+pub enum Resumed {
+    Yielded(Value),
+    // finished, returning the value
+    Returned(Value),
+}
+
+// Not derived, since the environment may well contain the generator itself.
+impl Debug for Generator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Generator")
+            .field("status", &self.status())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Generator {
+    /// A generator that will run the body in env, which has the function's arguments bound.
+    pub fn new(body: &[Decl], env: Environment) -> Self {
+        let outer = env.scope();
+        let machine = Machine {
+            env,
+            frames: vec![Frame::Block(body.iter().map(lower).collect(), 0, outer)],
+            receiver: None,
+        };
+
+        Self {
+            state: RefCell::new(State::Suspended(machine)),
+        }
+    }
+
+    /// "suspended" before the generator starts and at each yield, "running" while it runs, and
+    /// "done" once it has returned or failed.
+    pub fn status(&self) -> &'static str {
+        match *self.state.borrow() {
+            State::Suspended(_) => "suspended",
+            State::Running => "running",
+            State::Done => "done",
+        }
+    }
+
+    /// Runs the generator up to its next yield, or to the end of its body. The value becomes the
+    /// result of the yield it was suspended at, and is ignored when it starts.
+    pub fn resume(&self, value: Value, line: u32) -> Result<Resumed, ErrorState> {
+        let mut machine = match self.state.replace(State::Running) {
+            State::Suspended(machine) => machine,
+            state => {
+                let msg = match state {
+                    State::Running => "can't resume a generator from inside itself",
+                    _ => "can't resume a generator that has finished",
+                };
+                self.state.replace(state);
+                return Err(ErrorState::runtime_error(msg.to_string(), line));
+            }
+        };
+
+        // Resuming nests the generator's code in the caller's, like a call.
+        let resumed = count_call(line, || machine.run(value));
+        if let Ok(Resumed::Yielded(_)) = resumed {
+            self.state.replace(State::Suspended(machine));
+        } else {
+            self.state.replace(State::Done);
+        }
+
+        resumed
+    }
+
+    /// The next value the generator yields, or None if it has finished. What it returns is
+    /// ignored.
+    pub fn next(&self, line: u32) -> Result<Option<Value>, ErrorState> {
+        if let State::Done = *self.state.borrow() {
+            return Ok(None);
+        }
+
+        match self.resume(Value::Nil, line)? {
+            Resumed::Yielded(v) => Ok(Some(v)),
+            Resumed::Returned(_) => Ok(None),
+        }
+    }
+}
+
+// A statement of the body, with the ones that contain a yield broken down so they can be run a
+// piece at a time.
+enum Node {
+    // run in one go
+    Plain(Decl),
+    // the value to yield, and where the value it's resumed with goes
+    Yield(Option<Rc<Expr>>, Option<Receiver>),
+    Block(Rc<[Node]>),
+    If(Expr, Box<Node>, Option<Box<Node>>),
+    While(Rc<Expr>, Rc<Node>),
+    // initializer, condition, increment, body
+    For(Option<Decl>, Option<Rc<Expr>>, Option<Rc<Expr>>, Rc<Node>),
+    // loop variable, iterable, body
+    ForIn(String, Expr, Rc<Node>),
+}
+
+// The variable a yield's result is stored in.
+#[derive(Clone)]
+enum Receiver {
+    Var(String),
+    Const(String),
+    // an assignment, and its line
+    Assign(String, u32),
+}
+
+fn lower(decl: &Decl) -> Node {
+    match decl {
+        Decl::VarDecl(id, init) | Decl::ConstDecl(id, init) => match (&id.data, &init.data) {
+            (ExprData::Identifier(name), ExprData::Yield(value)) => {
+                let receiver = match decl {
+                    Decl::ConstDecl(..) => Receiver::Const(name.clone()),
+                    _ => Receiver::Var(name.clone()),
+                };
+                Node::Yield(value.clone(), Some(receiver))
+            }
+            _ => Node::Plain(decl.clone()),
+        },
+        Decl::Stmt(stmt) => lower_stmt(stmt),
+        decl => Node::Plain(decl.clone()),
+    }
+}
+
+fn lower_stmt(stmt: &Stmt) -> Node {
+    if !contains_yield(stmt) {
+        return Node::Plain(Decl::Stmt(stmt.clone()));
+    }
+
+    match stmt {
+        Stmt::Expr(e) => match &e.data {
+            ExprData::Yield(value) => Node::Yield(value.clone(), None),
+            ExprData::Assign(name, value) => match &value.data {
+                ExprData::Yield(value) => {
+                    Node::Yield(value.clone(), Some(Receiver::Assign(name.clone(), e.line)))
+                }
+                _ => unreachable!("only assignments of a yield contain one"),
+            },
+            _ => unreachable!("only yields and assignments of them contain one"),
+        },
+        Stmt::Block(decls) => Node::Block(decls.iter().map(lower).collect()),
+        Stmt::If(cond, then_branch, else_branch) => Node::If(
+            cond.clone(),
+            lower_stmt(then_branch).into(),
+            else_branch.as_ref().map(|stmt| lower_stmt(stmt).into()),
+        ),
+        Stmt::While(cond, body) => Node::While(cond.clone().into(), lower_stmt(body).into()),
+        Stmt::For(init, cond, incr, body) => Node::For(
+            init.as_deref().cloned(),
+            cond.clone().map(Rc::new),
+            incr.clone().map(Rc::new),
+            lower_stmt(body).into(),
+        ),
+        Stmt::ForIn(name, iterable, body) => {
+            Node::ForIn(name.clone(), iterable.clone(), lower_stmt(body).into())
+        }
+        _ => unreachable!("only blocks, ifs and loops contain yields"),
+    }
+}
+
+// Whether a statement has a yield in it, given where the parser allows them.
+fn contains_yield(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Expr(e) => match &e.data {
+            ExprData::Yield(_) => true,
+            ExprData::Assign(_, value) => matches!(value.data, ExprData::Yield(_)),
+            _ => false,
+        },
+        Stmt::Block(decls) => decls.iter().any(|decl| match decl {
+            Decl::VarDecl(_, init) | Decl::ConstDecl(_, init) => {
+                matches!(init.data, ExprData::Yield(_))
+            }
+            Decl::Stmt(stmt) => contains_yield(stmt),
+            _ => false,
+        }),
+        Stmt::If(_, then_branch, else_branch) => {
+            contains_yield(then_branch) || else_branch.as_deref().is_some_and(contains_yield)
+        }
+        Stmt::While(_, body) | Stmt::For(_, _, _, body) | Stmt::ForIn(_, _, body) => {
+            contains_yield(body)
+        }
+        _ => false,
+    }
+}
+
+// The blocks and loops the generator is inside, innermost last.
+enum Frame {
+    // the statements, the index of the next one to run, and the scope to go back to afterwards
+    Block(Rc<[Node]>, usize, Rc<RefCell<Scope>>),
+    While(Rc<Expr>, Rc<Node>),
+    For {
+        cond: Option<Rc<Expr>>,
+        incr: Option<Rc<Expr>>,
+        body: Rc<Node>,
+        // whether the body has run, so the increment is due
+        started: bool,
+        outer: Rc<RefCell<Scope>>,
+    },
+    ForIn {
+        name: String,
+        values: Values,
+        body: Rc<Node>,
+        outer: Rc<RefCell<Scope>>,
+    },
+}
+
+// What running a node led to.
+enum Step {
+    // it finished, or pushed a frame to carry on with
+    Next,
+    Yield(Value),
+    // a break, continue or return out of it
+    Jump(Flow),
+}
+
+struct Machine {
+    env: Environment,
+    frames: Vec<Frame>,
+    // where the value the generator is resumed with goes, if it's suspended at a yield that stores
+    // its result
+    receiver: Option<Receiver>,
+}
+
+impl Machine {
+    fn run(&mut self, value: Value) -> Result<Resumed, ErrorState> {
+        match self.receiver.take() {
+            Some(Receiver::Var(name)) => self.env.insert(name, value),
+            Some(Receiver::Const(name)) => self.env.insert_const(name, value),
+            Some(Receiver::Assign(name, line)) => self.env.assign(&name, value, line)?,
+            None => (),
+        }
+
+        loop {
+            let step = match self.frames.last_mut() {
+                None => return Ok(Resumed::Returned(Value::Nil)),
+
+                Some(Frame::Block(nodes, next, outer)) => {
+                    if *next == nodes.len() {
+                        self.env = Environment::with_scope(outer.clone());
+                        self.frames.pop();
+                        continue;
+                    }
+
+                    let (nodes, i) = (nodes.clone(), *next);
+                    *next += 1;
+                    self.start(&nodes[i])?
+                }
+
+                Some(Frame::While(cond, body)) => {
+                    if !cond.eval(&mut self.env)?.is_truthy() {
+                        self.frames.pop();
+                        continue;
+                    }
+
+                    let body = body.clone();
+                    self.start(&body)?
+                }
+
+                Some(Frame::For {
+                    cond,
+                    incr,
+                    body,
+                    started,
+                    outer,
+                }) => {
+                    if let (true, Some(incr)) = (*started, incr) {
+                        incr.eval(&mut self.env)?;
+                    }
+                    *started = true;
+
+                    if let Some(cond) = cond {
+                        if !cond.eval(&mut self.env)?.is_truthy() {
+                            self.env = Environment::with_scope(outer.clone());
+                            self.frames.pop();
+                            continue;
+                        }
+                    }
+
+                    let body = body.clone();
+                    self.start(&body)?
+                }
+
+                Some(Frame::ForIn {
+                    name,
+                    values,
+                    body,
+                    outer,
+                }) => {
+                    // each iteration gets its own scope
+                    self.env = Environment::with_scope(outer.clone());
+                    let Some(v) = values.next().transpose()? else {
+                        self.frames.pop();
+                        continue;
+                    };
+
+                    self.env = Environment::with_scope(Scope::new_child(outer.clone()));
+                    self.env.insert(name.clone(), v);
+                    let body = body.clone();
+                    self.start(&body)?
+                }
+            };
+
+            match step {
+                Step::Next => (),
+                Step::Yield(v) => return Ok(Resumed::Yielded(v)),
+                Step::Jump(Flow::Return(v)) => return Ok(Resumed::Returned(v)),
+                Step::Jump(flow) => self.unwind(flow),
+            }
+        }
+    }
+
+    // Starts running a node, in one go if it's plain.
+    fn start(&mut self, node: &Node) -> Result<Step, ErrorState> {
+        match node {
+            Node::Plain(decl) => {
+                return match decl.exec(&mut self.env)? {
+                    Flow::Normal => Ok(Step::Next),
+                    flow => Ok(Step::Jump(flow)),
+                }
+            }
+            Node::Yield(e, receiver) => {
+                let v = match e {
+                    Some(e) => e.eval(&mut self.env)?,
+                    None => Value::Nil,
+                };
+                self.receiver = receiver.clone();
+                return Ok(Step::Yield(v));
+            }
+            Node::Block(nodes) => {
+                let outer = self.enter_scope();
+                self.frames.push(Frame::Block(nodes.clone(), 0, outer));
+            }
+            Node::If(cond, then_branch, else_branch) => {
+                if cond.eval(&mut self.env)?.is_truthy() {
+                    return self.start(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.start(else_branch);
+                }
+            }
+            Node::While(cond, body) => self.frames.push(Frame::While(cond.clone(), body.clone())),
+            Node::For(init, cond, incr, body) => {
+                // the loop variable is scoped to the loop
+                let outer = self.enter_scope();
+                if let Some(init) = init {
+                    init.exec(&mut self.env)?;
+                }
+                self.frames.push(Frame::For {
+                    cond: cond.clone(),
+                    incr: incr.clone(),
+                    body: body.clone(),
+                    started: false,
+                    outer,
+                });
+            }
+            Node::ForIn(name, iterable, body) => {
+                let values = Values::new(&iterable.eval(&mut self.env)?, iterable.line)?;
+                self.frames.push(Frame::ForIn {
+                    name: name.clone(),
+                    values,
+                    body: body.clone(),
+                    outer: self.env.scope(),
+                });
+            }
+        }
+
+        Ok(Step::Next)
+    }
+
+    // Moves into a new scope nested in the current one, returning the current one.
+    fn enter_scope(&mut self) -> Rc<RefCell<Scope>> {
+        let outer = self.env.scope();
+        self.env = Environment::with_scope(Scope::new_child(outer.clone()));
+        outer
+    }
+
+    // Pops the frames inside the innermost loop for a break or continue, and the loop itself for a
+    // break.
+    fn unwind(&mut self, flow: Flow) {
+        while let Some(frame) = self.frames.pop() {
+            let outer = match &frame {
+                Frame::Block(_, _, outer) => {
+                    self.env = Environment::with_scope(outer.clone());
+                    continue;
+                }
+                Frame::While(..) => None,
+                Frame::For { outer, .. } | Frame::ForIn { outer, .. } => Some(outer.clone()),
+            };
+
+            match flow {
+                Flow::Continue => self.frames.push(frame),
+                _ => {
+                    if let Some(outer) = outer {
+                        self.env = Environment::with_scope(outer);
+                    }
+                }
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util;
+
+    // Runs a program, returning the value of its global `out`.
+    fn run(source: &str) -> String {
+        test_util::global(&mut test_util::run(source), "out")
+    }
+
+    #[test]
+    fn resuming() {
+        let out = run("
+            var g = fun* (n) { yield n; yield n + 1; return \"done\"; }(1);
+            var out = [status(g)];
+            for (var i = 0; i < 3; i++) {
+                push(out, resume(g));
+                push(out, status(g));
+            }
+        ");
+        assert_eq!(out, "[suspended, 1, suspended, 2, suspended, done, done]");
+    }
+
+    #[test]
+    fn sending_values() {
+        let out = run("
+            var g = fun* () {
+                var a = yield \"first\";
+                const b = yield a;
+                var c = nil;
+                for (var i = 0; i < 2; i++) {
+                    c = yield [a, b, i];
+                }
+                yield c;
+            }();
+            // the value a generator starts with is ignored
+            var out = [resume(g, 0), resume(g, 1), resume(g, 2), resume(g, 3), resume(g, 4)];
+        ");
+        assert_eq!(out, "[first, 1, [1, 2, 0], [1, 2, 1], 4]");
+
+        // for-in and plain resume() send nil
+        let out = run("
+            var g = fun* () { var x = yield 1; yield x; }();
+            var out = [];
+            for (x in g) push(out, x);
+        ");
+        assert_eq!(out, "[1, nil]");
+    }
+
+    #[test]
+    fn loops() {
+        let out = run("
+            var evens = fun* (n) {
+                for (var i = 0; ; i++) {
+                    if (i == n) break;
+                    if (i % 2 == 1) continue;
+                    yield i;
+                }
+                var i = \"after\";
+                yield i;
+            };
+            var out = [];
+            for (x in evens(5)) push(out, x);
+        ");
+        assert_eq!(out, "[0, 2, 4, after]");
+
+        // each iteration of a for-in gets its own variable
+        let out = run("
+            var g = fun* () { for (x in [1, 2]) yield () => x; };
+            var out = [];
+            for (f in g()) push(out, f);
+            out = [out[0](), out[1]()];
+        ");
+        assert_eq!(out, "[1, 2]");
+    }
+
+    #[test]
+    fn returning_from_loops() {
+        let out = run("
+            var g = fun* () {
+                while (true) {
+                    for (x in [1, 2, 3]) {
+                        yield x;
+                        if (x == 2) return x * 10;
+                    }
+                }
+            }();
+            var out = [resume(g), resume(g), resume(g), status(g)];
+        ");
+        assert_eq!(out, "[1, 2, 20, done]");
+    }
+
+    #[test]
+    fn recursion() {
+        let out = run("
+            var upto = fun* (n) {
+                if (n == 0) return;
+                for (x in upto(n - 1)) yield x;
+                yield n;
+            };
+            var out = [];
+            for (x in upto(4)) push(out, x);
+        ");
+        assert_eq!(out, "[1, 2, 3, 4]");
+    }
+
+    #[test]
+    fn errors() {
+        // an error finishes the generator
+        let out = run("
+            var g = fun* () { yield 1; nil + 1; yield 2; }();
+            resume(g);
+            var out = [];
+            try { resume(g); } catch (e) { push(out, \"caught\"); }
+            push(out, status(g));
+            try { resume(g); } catch (e) { push(out, e[\"message\"]); }
+        ");
+        assert_eq!(
+            out,
+            "[caught, done, can't resume a generator that has finished]"
+        );
+
+        let out = run("
+            var g = nil;
+            g = fun* () { resume(g); yield 1; }();
+            var out = nil;
+            try { resume(g); } catch (e) { out = e[\"message\"]; }
+        ");
+        assert_eq!(out, "can't resume a generator from inside itself");
+    }
+}
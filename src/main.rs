@@ -1,19 +1,32 @@
+mod bigint;
+mod class;
 mod config;
 mod error;
 mod eval;
 mod exec;
 mod expr;
+mod function;
+mod generator;
+mod map;
+mod module;
+mod native;
 mod parser;
 mod pretty;
+mod resolver;
 mod scanner;
+#[cfg(test)]
+mod test_util;
 mod token;
 
 use std::fs;
 use std::io::{self, Write};
+use std::thread;
 
 use config::Config;
-use error::ErrorState;
+use error::{Error, ErrorState};
 use exec::ExecState;
+use expr::Program;
+use token::{Token, TokenData};
 
 use clap::Parser;
 
@@ -22,6 +35,34 @@ fn print_prompt() {
     io::stdout().flush().unwrap();
 }
 
+fn print_warnings(warnings: &[Error]) {
+    for warning in warnings {
+        eprintln!("{warning}");
+    }
+}
+
+// Parses a line of the REPL. If it doesn't parse as is, it's tried again with a semicolon at the
+// end, to allow bare expressions like `1 + 2` and declarations like `var f = fun () {}`.
+fn parse_line(mut tokens: Vec<Token>) -> Result<Program, ErrorState> {
+    let err = match parser::parse(tokens.clone()) {
+        Ok(program) => return Ok(program),
+        Err(err) => err,
+    };
+
+    let eof = tokens.pop().expect("scan ends with Eof");
+    if let Some(Token {
+        data: TokenData::Semicolon,
+        ..
+    }) = tokens.last()
+    {
+        return Err(err);
+    }
+    tokens.push(Token::new(TokenData::Semicolon, eof.line));
+    tokens.push(eof);
+
+    parser::parse(tokens).map_err(|_| err)
+}
+
 fn repl(options: config::Config) -> Result<(), ErrorState> {
     print_prompt();
 
@@ -30,7 +71,7 @@ fn repl(options: config::Config) -> Result<(), ErrorState> {
     // Line will be None if someone hits ^D
     for (lineno, line) in io::stdin().lines().enumerate() {
         let line = line.unwrap();
-        let mut line = line.trim().to_string();
+        let line = line.trim();
 
         // Skip the line if there's only whitespace
         if line.is_empty() {
@@ -38,12 +79,7 @@ fn repl(options: config::Config) -> Result<(), ErrorState> {
             continue;
         }
 
-        if !line.ends_with(';') {
-            // Helpfully append a semicolon to allow bare expressions in the repl.
-            line.push(';');
-        }
-
-        let tokens = match scanner::scan(&line, lineno as u32) {
+        let tokens = match scanner::scan(line, lineno as u32) {
             Ok(v) => v,
             Err(err) => {
                 println!("{err}");
@@ -52,7 +88,7 @@ fn repl(options: config::Config) -> Result<(), ErrorState> {
             }
         };
 
-        let program = match parser::parse(tokens) {
+        let program = match parse_line(tokens) {
             Ok(program) => program,
             Err(err) => {
                 println!("{err}");
@@ -61,6 +97,15 @@ fn repl(options: config::Config) -> Result<(), ErrorState> {
             }
         };
 
+        match state.resolve(&program) {
+            Ok(warnings) => print_warnings(&warnings),
+            Err(err) => {
+                println!("{err}");
+                print_prompt();
+                continue;
+            }
+        }
+
         let _ = state.exec(program).map_err(|e| println!("{e}"));
 
         print_prompt();
@@ -80,14 +125,14 @@ fn process_file(options: Config) -> Result<(), ErrorState> {
     let program = parser::parse(tokens)?;
 
     let mut state = ExecState::new(options);
+    print_warnings(&state.resolve(&program)?);
 
     let _ = state.exec(program).map_err(|e| println!("{e}"));
 
     Ok(())
 }
 
-fn main() {
-    let args = Config::parse();
+fn run(args: Config) {
     let err = match args.file {
         Some(_) => process_file(args),
         None => repl(args),
@@ -98,3 +143,20 @@ fn main() {
         std::process::exit(65);
     }
 }
+
+// Each Lox call nests a few dozen Rust calls, so the interpreter runs on a thread with a stack big
+// enough for function::MAX_CALL_DEPTH of them.
+const STACK_SIZE: usize = 1 << 30;
+
+fn main() {
+    let args = Config::parse();
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| run(args))
+        .expect("Should have been able to start the interpreter thread");
+
+    // The thread has already reported its panic, so just exit the way a panic on this one would.
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::bigint::BigInt;
+use crate::eval::Value;
+
+/// The subset of values that can be used as map keys.
+///
+/// Numbers that compare equal map to the same key, so `m[1]` and `m[1.0]` are the same entry.
+/// Integral floats are therefore stored as integers, and come back out of `keys()` as integers.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Boolean(bool),
+    Int(i64),
+    BigInt(BigInt),
+    // non-integral floats, by bit pattern
+    Number(u64),
+    String(String),
+}
+
+impl MapKey {
+    pub fn from_value(v: &Value) -> Result<Self, String> {
+        let key = match v {
+            Value::Nil => Self::Nil,
+            Value::Boolean(b) => Self::Boolean(*b),
+            Value::Int(n) => Self::Int(*n),
+            Value::BigInt(n) => Self::BigInt(n.clone()),
+            Value::Number(n) if n.is_nan() => return Err("NaN can't be used as a map key".into()),
+            // integral floats become the integer they equal exactly (and -0 becomes 0), as an Int
+            // if it fits like any other integer value
+            Value::Number(n) => match BigInt::from_f64(*n) {
+                Some(big) => match big.to_i64() {
+                    Some(n) => Self::Int(n),
+                    None => Self::BigInt(big),
+                },
+                None => Self::Number(n.to_bits()),
+            },
+            Value::String(s) => Self::String(s.clone()),
+            v => return Err(format!("{v} can't be used as a map key")),
+        };
+
+        Ok(key)
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Nil => Value::Nil,
+            Self::Boolean(b) => Value::Boolean(*b),
+            Self::Int(n) => Value::Int(*n),
+            Self::BigInt(n) => Value::BigInt(n.clone()),
+            Self::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Self::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+/// A map that iterates in insertion order.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(MapKey, Value)>,
+    // position of each key in entries
+    index: HashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, k: &MapKey) -> Option<&Value> {
+        self.index.get(k).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, k: &MapKey) -> bool {
+        self.index.contains_key(k)
+    }
+
+    /// Updates the value in place if the key exists, otherwise appends a new entry.
+    pub fn insert(&mut self, k: MapKey, v: Value) {
+        match self.index.get(&k) {
+            Some(&i) => self.entries[i].1 = v,
+            None => {
+                self.index.insert(k.clone(), self.entries.len());
+                self.entries.push((k, v));
+            }
+        }
+    }
+
+    // O(n), since later entries have to shift down to keep insertion order.
+    pub fn remove(&mut self, k: &MapKey) -> Option<Value> {
+        let i = self.index.remove(k)?;
+        let (_, v) = self.entries.remove(i);
+
+        for idx in self.index.values_mut() {
+            if *idx > i {
+                *idx -= 1;
+            }
+        }
+
+        Some(v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, Value)> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Map, MapKey};
+    use crate::bigint::BigInt;
+    use crate::eval::Value;
+
+    fn key(v: Value) -> MapKey {
+        MapKey::from_value(&v).unwrap()
+    }
+
+    #[test]
+    fn numeric_keys() {
+        assert_eq!(key(Value::Int(1)), key(Value::Number(1.0)));
+        assert_eq!(key(Value::Number(-0.0)), key(Value::Int(0)));
+        assert_ne!(key(Value::Number(1.5)), key(Value::Int(1)));
+
+        // too big for an Int, but still equal to the integer
+        let big = BigInt::parse_radix("100000000000000000000", 10).unwrap();
+        assert_eq!(key(Value::Number(1e20)), key(Value::BigInt(big)));
+        assert_eq!(
+            key(Value::Number(-9223372036854775808.0)),
+            key(Value::Int(i64::MIN))
+        );
+        assert!(MapKey::from_value(&Value::Number(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut m = Map::new();
+        for k in ["a", "b", "c", "d"] {
+            m.insert(key(Value::String(k.to_string())), Value::Nil);
+        }
+
+        // updating keeps the original position
+        m.insert(key(Value::String("a".to_string())), Value::Int(1));
+        assert_eq!(
+            m.remove(&key(Value::String("b".to_string()))),
+            Some(Value::Nil)
+        );
+        m.insert(key(Value::String("b".to_string())), Value::Nil);
+
+        let keys = m
+            .iter()
+            .map(|(k, _)| k.to_value().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "c", "d", "b"]);
+        assert_eq!(
+            m.get(&key(Value::String("a".to_string()))),
+            Some(&Value::Int(1))
+        );
+        assert_eq!(
+            m.get(&key(Value::String("d".to_string()))),
+            Some(&Value::Nil)
+        );
+        assert_eq!(m.len(), 4);
+    }
+}
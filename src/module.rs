@@ -0,0 +1,292 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::error::ErrorState;
+use crate::eval::Value;
+use crate::exec::{Environment, Flow};
+use crate::expr::{Decl, ExprData};
+use crate::map::{Map, MapKey};
+use crate::resolver::Resolver;
+use crate::{parser, scanner};
+
+/// Loads modules for `import`, and remembers the ones that have been loaded.
+///
+/// A module is a file that runs once, in its own global scope, the first time it's imported. Its
+/// value is a map from the names it exports to their values when it finished running. Import paths
+/// are looked up relative to the directory of the importing file, then in each directory of the
+/// `RLOX_PATH` environment variable.
+pub struct Modules {
+    // the exports of each module, by canonical path
+    loaded: HashMap<PathBuf, Value>,
+    // modules that are being run, outermost first, to detect cycles
+    loading: Vec<PathBuf>,
+    search_path: Vec<PathBuf>,
+}
+
+impl Modules {
+    /// `main` is the file being run, if any, so that modules importing it are reported as cycles.
+    pub fn new(main: Option<&Path>) -> Self {
+        let search_path = match std::env::var_os("RLOX_PATH") {
+            Some(paths) => std::env::split_paths(&paths).collect(),
+            None => vec![],
+        };
+
+        Self::with_search_path(main, search_path)
+    }
+
+    // Searches the given directories instead of RLOX_PATH's.
+    fn with_search_path(main: Option<&Path>, search_path: Vec<PathBuf>) -> Self {
+        Self {
+            loaded: HashMap::new(),
+            loading: main
+                .and_then(|p| p.canonicalize().ok())
+                .into_iter()
+                .collect(),
+            search_path,
+        }
+    }
+
+    /// Executes a top-level declaration of the file in `dir`.
+    pub fn exec_decl(
+        &mut self,
+        decl: &Decl,
+        env: &mut Environment,
+        dir: &Path,
+    ) -> Result<Flow, ErrorState> {
+        match decl {
+            Decl::Import(path, name, line) => {
+                let module = self.import(path, dir, *line)?;
+                env.insert(name.clone(), module);
+                Ok(Flow::Normal)
+            }
+            decl => decl.exec(env),
+        }
+    }
+
+    fn import(&mut self, path: &str, dir: &Path, line: u32) -> Result<Value, ErrorState> {
+        let resolved = self
+            .resolve(path, dir)
+            .ok_or_else(|| ErrorState::runtime_error(format!("can't find module {path}"), line))?;
+
+        if let Some(module) = self.loaded.get(&resolved) {
+            return Ok(module.clone());
+        }
+
+        if let Some(i) = self.loading.iter().position(|p| *p == resolved) {
+            let cycle = self.loading[i..]
+                .iter()
+                .chain([&resolved])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(ErrorState::runtime_error(
+                format!("cyclic import: {cycle}"),
+                line,
+            ));
+        }
+
+        let contents = fs::read_to_string(&resolved).map_err(|e| {
+            ErrorState::runtime_error(format!("can't read module {path}: {e}"), line)
+        })?;
+
+        self.loading.push(resolved.clone());
+        let result = self.run(&contents, &resolved);
+        self.loading.pop();
+
+        let module = result.map_err(|e| match e {
+            // values thrown by the module propagate as they are
+            ErrorState::Thrown(..) => e,
+            e => ErrorState::runtime_error(format!("error in module {path}:\n{e}"), line),
+        })?;
+
+        self.loaded.insert(resolved, module.clone());
+        Ok(module)
+    }
+
+    // The first existing file, as a canonical path.
+    fn resolve(&self, path: &str, dir: &Path) -> Option<PathBuf> {
+        std::iter::once(dir)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .find_map(|d| d.join(path).canonicalize().ok())
+    }
+
+    // Unlike the main program, a module stops at its first error.
+    fn run(&mut self, contents: &str, path: &Path) -> Result<Value, ErrorState> {
+        let program = parser::parse(scanner::scan(contents, 0)?)?;
+        for warning in Resolver::new().resolve(&program)? {
+            eprintln!("{warning}");
+        }
+        let dir = path.parent().unwrap();
+
+        let mut env = Environment::new();
+        for decl in &program {
+            self.exec_decl(decl, &mut env, dir)?;
+        }
+
+        let mut exports = Map::new();
+        for decl in &program {
+            if let Decl::Export(decl) = decl {
+                let name = match decl.as_ref() {
+                    Decl::VarDecl(id, _) | Decl::ConstDecl(id, _) => match &id.data {
+                        ExprData::Identifier(name) => name,
+                        _ => continue,
+                    },
+                    Decl::Class(class) => &class.name,
+                    Decl::Trait(t) => &t.name,
+                    _ => continue,
+                };
+                let v = env.get(name).unwrap_or(Value::Nil);
+                exports.insert(MapKey::String(name.clone()), v);
+            }
+        }
+
+        Ok(Value::Map(Rc::new(RefCell::new(exports))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::Modules;
+    use crate::error::ErrorState;
+    use crate::eval::Value;
+    use crate::exec::Environment;
+    use crate::test_util::parse;
+
+    // A fresh directory holding the given files, which is removed when dropped.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("rlox-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            for (path, contents) in files {
+                let path = dir.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+
+            Self(dir)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Runs a program as if it were a file in dir, returning its global scope.
+    fn run(modules: &mut Modules, dir: &Path, source: &str) -> Result<Environment, ErrorState> {
+        let program = parse(source);
+        let mut env = Environment::new();
+        for decl in &program {
+            modules.exec_decl(decl, &mut env, dir)?;
+        }
+
+        Ok(env)
+    }
+
+    fn get(env: &mut Environment, name: &str) -> Value {
+        env.get(&name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn relative_paths() {
+        // lib/a.lox's import is relative to lib, not to the importing program
+        let fixture = Fixture::new(
+            "relative",
+            &[
+                (
+                    "lib/a.lox",
+                    "import \"b.lox\" as b; export var x = b[\"y\"] + 1;",
+                ),
+                ("lib/b.lox", "export var y = 1;"),
+                ("b.lox", "export var y = 10;"),
+            ],
+        );
+
+        let mut modules = Modules::with_search_path(None, vec![]);
+        let mut env = run(&mut modules, &fixture.0, "import \"lib/a.lox\" as a;").unwrap();
+        assert_eq!(get(&mut env, "a").to_string(), "{x: 2}");
+
+        let err = run(&mut modules, &fixture.0, "import \"nope.lox\" as n;")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("can't find module nope.lox"));
+    }
+
+    #[test]
+    fn modules_are_cached() {
+        let fixture = Fixture::new("cached", &[("lib/m.lox", "export var xs = [];")]);
+
+        // the same file by another path is the same module
+        let mut modules = Modules::with_search_path(None, vec![]);
+        let mut env = run(
+            &mut modules,
+            &fixture.0,
+            "import \"lib/m.lox\" as m1; import \"lib/../lib/m.lox\" as m2; push(m1[\"xs\"], 1);",
+        )
+        .unwrap();
+        assert_eq!(get(&mut env, "m2").to_string(), "{xs: [1]}");
+    }
+
+    #[test]
+    fn search_path() {
+        let fixture = Fixture::new(
+            "search",
+            &[
+                ("first/m.lox", "export var from = \"first\";"),
+                ("second/m.lox", "export var from = \"second\";"),
+                ("second/n.lox", "export var from = \"second\";"),
+                ("main/n.lox", "export var from = \"main\";"),
+            ],
+        );
+
+        // the importing file's directory comes first, then the search path in order
+        let dirs = vec![fixture.0.join("first"), fixture.0.join("second")];
+        let mut modules = Modules::with_search_path(None, dirs);
+        let mut env = run(
+            &mut modules,
+            &fixture.0.join("main"),
+            "import \"m.lox\" as m; import \"n.lox\" as n;",
+        )
+        .unwrap();
+        assert_eq!(get(&mut env, "m").to_string(), "{from: first}");
+        assert_eq!(get(&mut env, "n").to_string(), "{from: main}");
+    }
+
+    #[test]
+    fn cycles() {
+        let fixture = Fixture::new(
+            "cycles",
+            &[
+                ("main.lox", "import \"a.lox\" as a;"),
+                ("a.lox", "import \"b.lox\" as b;"),
+                ("b.lox", "import \"a.lox\" as a;"),
+                ("c.lox", "import \"main.lox\" as main;"),
+            ],
+        );
+
+        let mut modules = Modules::with_search_path(None, vec![]);
+        let err = run(&mut modules, &fixture.0, "import \"a.lox\" as a;")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("cyclic import"));
+        assert!(err.to_string().contains("a.lox -> "));
+
+        // importing the main file is a cycle too
+        let main = fixture.0.join("main.lox");
+        let mut modules = Modules::with_search_path(Some(&main), vec![]);
+        let err = run(&mut modules, &fixture.0, "import \"c.lox\" as c;")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("cyclic import"));
+    }
+}
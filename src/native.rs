@@ -0,0 +1,309 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::ErrorState;
+use crate::eval::{map_key, resolve_index, Value};
+use crate::function::{Args, Signature};
+use crate::generator::Resumed;
+
+/// A function implemented in Rust that is callable from Lox.
+///
+/// Natives take parameters like Lox functions do: they can have defaults, be passed by name, and
+/// be followed by a rest parameter. `func` gets the value of each parameter in order, followed by
+/// any extra arguments if the native is variadic.
+#[derive(Debug)]
+pub struct NativeFn {
+    pub name: &'static str,
+    pub params: &'static [NativeParam],
+    pub variadic: bool,
+    pub func: fn(&[Value], u32) -> Result<Value, ErrorState>,
+}
+
+#[derive(Debug)]
+pub struct NativeParam {
+    pub name: &'static str,
+    pub default: Option<Value>,
+}
+
+const fn param(name: &'static str) -> NativeParam {
+    NativeParam {
+        name,
+        default: None,
+    }
+}
+
+impl NativeFn {
+    pub fn call(&self, args: Args, line: u32) -> Result<Value, ErrorState> {
+        let signature = Signature {
+            name: self.name,
+            params: self
+                .params
+                .iter()
+                .map(|p| (p.name, p.default.is_some()))
+                .collect(),
+            variadic: self.variadic,
+        };
+        let (slots, rest) = signature.bind(args, line)?;
+
+        let mut vals = slots
+            .into_iter()
+            .zip(self.params)
+            .map(|(slot, p)| slot.or_else(|| p.default.clone()).unwrap())
+            .collect::<Vec<_>>();
+        vals.extend(rest);
+
+        (self.func)(&vals, line)
+    }
+}
+
+/// Natives defined in the global scope of every program.
+pub const NATIVES: &[NativeFn] = &[
+    NativeFn {
+        name: "len",
+        params: &[param("value")],
+        variadic: false,
+        func: len,
+    },
+    NativeFn {
+        name: "push",
+        params: &[param("list")],
+        variadic: true,
+        func: push,
+    },
+    NativeFn {
+        name: "pop",
+        params: &[
+            param("list"),
+            NativeParam {
+                name: "index",
+                default: Some(Value::Int(-1)),
+            },
+        ],
+        variadic: false,
+        func: pop,
+    },
+    NativeFn {
+        name: "keys",
+        params: &[param("map")],
+        variadic: false,
+        func: keys,
+    },
+    NativeFn {
+        name: "values",
+        params: &[param("map")],
+        variadic: false,
+        func: values,
+    },
+    NativeFn {
+        name: "has",
+        params: &[param("map"), param("key")],
+        variadic: false,
+        func: has,
+    },
+    NativeFn {
+        name: "remove",
+        params: &[param("map"), param("key")],
+        variadic: false,
+        func: remove,
+    },
+    NativeFn {
+        name: "resume",
+        params: &[
+            param("generator"),
+            NativeParam {
+                name: "value",
+                default: Some(Value::Nil),
+            },
+        ],
+        variadic: false,
+        func: resume,
+    },
+    NativeFn {
+        name: "status",
+        params: &[param("generator")],
+        variadic: false,
+        func: status,
+    },
+];
+
+// Strings are measured in grapheme clusters, not bytes.
+fn len(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    let len = match &args[0] {
+        Value::List(l) => l.borrow().len(),
+        Value::Map(m) => m.borrow().len(),
+        Value::String(s) => s.graphemes(true).count(),
+        v => {
+            return Err(ErrorState::runtime_error(
+                format!("len: expected a list, map or string, got {v}"),
+                line,
+            ))
+        }
+    };
+
+    Ok(Value::Int(len as i64))
+}
+
+// Appends any number of values.
+fn push(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::List(l) => {
+            l.borrow_mut().extend_from_slice(&args[1..]);
+            Ok(Value::Nil)
+        }
+        v => Err(ErrorState::runtime_error(
+            format!("push: expected a list, got {v}"),
+            line,
+        )),
+    }
+}
+
+// Removes the element at the index, the last one by default.
+fn pop(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::List(l) => {
+            let mut l = l.borrow_mut();
+            if l.is_empty() {
+                return Err(ErrorState::runtime_error("pop: list is empty".into(), line));
+            }
+
+            let i = resolve_index(&args[1], l.len(), line)?;
+            Ok(l.remove(i))
+        }
+        v => Err(ErrorState::runtime_error(
+            format!("pop: expected a list, got {v}"),
+            line,
+        )),
+    }
+}
+
+fn new_list(vals: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(vals)))
+}
+
+// Map natives return entries in insertion order.
+
+fn keys(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Map(m) => Ok(new_list(
+            m.borrow().iter().map(|(k, _)| k.to_value()).collect(),
+        )),
+        v => Err(ErrorState::runtime_error(
+            format!("keys: expected a map, got {v}"),
+            line,
+        )),
+    }
+}
+
+fn values(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Map(m) => Ok(new_list(
+            m.borrow().iter().map(|(_, v)| v.clone()).collect(),
+        )),
+        v => Err(ErrorState::runtime_error(
+            format!("values: expected a map, got {v}"),
+            line,
+        )),
+    }
+}
+
+fn has(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Map(m) => Ok(Value::Boolean(
+            m.borrow().contains_key(&map_key(&args[1], line)?),
+        )),
+        v => Err(ErrorState::runtime_error(
+            format!("has: expected a map, got {v}"),
+            line,
+        )),
+    }
+}
+
+// Returns the removed value.
+fn remove(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Map(m) => m
+            .borrow_mut()
+            .remove(&map_key(&args[1], line)?)
+            .ok_or_else(|| {
+                ErrorState::runtime_error(format!("remove: key {} not found", args[1]), line)
+            }),
+        v => Err(ErrorState::runtime_error(
+            format!("remove: expected a map, got {v}"),
+            line,
+        )),
+    }
+}
+
+// Runs a generator to its next yield and returns the yielded value, or returns what the generator
+// returns if it finishes instead. The value is what the yield it was suspended at evaluates to.
+fn resume(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Generator(g) => match g.resume(args[1].clone(), line)? {
+            Resumed::Yielded(v) | Resumed::Returned(v) => Ok(v),
+        },
+        v => Err(ErrorState::runtime_error(
+            format!("resume: expected a generator, got {v}"),
+            line,
+        )),
+    }
+}
+
+fn status(args: &[Value], line: u32) -> Result<Value, ErrorState> {
+    match &args[0] {
+        Value::Generator(g) => Ok(Value::String(g.status().to_string())),
+        v => Err(ErrorState::runtime_error(
+            format!("status: expected a generator, got {v}"),
+            line,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::NATIVES;
+    use crate::eval::Value;
+    use crate::function::Args;
+
+    // Calls a native with integer arguments, the first of which is a list.
+    fn call(
+        name: &str,
+        list: &Rc<RefCell<Vec<Value>>>,
+        positional: &[i64],
+        named: &[(&str, i64)],
+    ) -> Result<Value, String> {
+        let native = NATIVES.iter().find(|n| n.name == name).unwrap();
+        let args = Args {
+            positional: std::iter::once(Value::List(list.clone()))
+                .chain(positional.iter().map(|&n| Value::Int(n)))
+                .collect(),
+            named: named
+                .iter()
+                .map(|&(name, n)| (name.to_string(), Value::Int(n)))
+                .collect(),
+        };
+        native.call(args, 0).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn default_parameters() {
+        let list = Rc::new(RefCell::new((1..=5).map(Value::Int).collect()));
+
+        // the index defaults to -1
+        assert_eq!(call("pop", &list, &[], &[]), Ok(Value::Int(5)));
+        assert_eq!(call("pop", &list, &[0], &[]), Ok(Value::Int(1)));
+        assert_eq!(call("pop", &list, &[], &[("index", 1)]), Ok(Value::Int(3)));
+        assert_eq!(*list.borrow(), [Value::Int(2), Value::Int(4)]);
+
+        assert!(call("pop", &list, &[2], &[])
+            .unwrap_err()
+            .contains("out of bounds"));
+        assert!(call("pop", &list, &[0, 1], &[])
+            .unwrap_err()
+            .contains("expected 1 to 2 arguments"));
+    }
+}
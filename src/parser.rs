@@ -1,5 +1,11 @@
+use std::rc::Rc;
+
 use crate::error::{Error, ErrorState};
-use crate::expr::{BinOp, Decl, Expr, ExprData, Program, Stmt, UnaryOp};
+use crate::eval::Value;
+use crate::expr::{
+    BinOp, ClassDecl, Decl, Expr, ExprData, FunctionBody, FunctionDecl, MatchArm, Method,
+    MethodKind, Param, Pattern, Program, Stmt, TraitDecl, UnaryOp,
+};
 use crate::token::{
     Token,
     TokenData::{self, *},
@@ -10,9 +16,34 @@ pub fn parse(tokens: Vec<Token>) -> Result<Program, ErrorState> {
     p.parse()
 }
 
+// The positional and named arguments of a call.
+type CallArgs = (Vec<Expr>, Vec<(String, Expr)>);
+
 struct Parser {
     tokens: Vec<Token>,
     idx: usize,
+
+    // How many function bodies we're inside, to reject `return` at the top level.
+    function_depth: usize,
+    // How many loops we're inside in the current function, to reject stray `break`/`continue`.
+    loop_depth: usize,
+    // The classes and traits we're inside, innermost last, to reject `this` and `super` outside of
+    // methods.
+    classes: Vec<ClassKind>,
+    // Whether we're in the body of an `init` method, where `return` can't have a value.
+    in_initializer: bool,
+    // Whether a `yield` is allowed here: in a generator's body, but not inside a try or match.
+    can_yield: bool,
+    // Whether we're in a match guard, outside of any brackets, where `(...) =>` ends the guard
+    // instead of starting an arrow function.
+    in_guard: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ClassKind {
+    Class,
+    Subclass,
+    Trait,
 }
 
 macro_rules! recurse_binary_expr {
@@ -38,7 +69,16 @@ macro_rules! recurse_binary_expr {
 
 impl Parser {
     fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, idx: 0 }
+        Self {
+            tokens,
+            idx: 0,
+            function_depth: 0,
+            loop_depth: 0,
+            classes: vec![],
+            in_initializer: false,
+            can_yield: false,
+            in_guard: false,
+        }
     }
 
     /* Utilities for interacting with the token array */
@@ -54,6 +94,11 @@ impl Parser {
         &self.tokens[self.idx]
     }
 
+    fn peek_at(&self, offset: usize) -> &Token {
+        let idx = (self.idx + offset).min(self.tokens.len() - 1);
+        &self.tokens[idx]
+    }
+
     fn expect(&mut self, expected: TokenData, err: &'static str) -> Result<(), Error> {
         let next_token = self.peek();
         if expected == next_token.data {
@@ -79,7 +124,7 @@ impl Parser {
         let mut err_state = ErrorState::new_parser_state();
 
         while !self.is_at_end() {
-            match self.declaration() {
+            match self.top_level_declaration() {
                 Ok(expr) => program.push(expr),
                 Err(e) => {
                     err_state.add(e);
@@ -114,6 +159,57 @@ impl Parser {
         }
     }
 
+    fn top_level_declaration(&mut self) -> Result<Decl, Error> {
+        let Token { data, line } = self.peek();
+        let line = *line;
+
+        match data {
+            // 'import' STRING 'as' IDENTIFIER ;
+            Import => {
+                self.next();
+
+                let path = match &self.peek().data {
+                    StringToken(s) => s.clone(),
+                    t => {
+                        return Err(Error::parse_error(
+                            format!("expected a module path string, got {t:?}"),
+                            line,
+                        ))
+                    }
+                };
+                self.next();
+
+                self.expect(TokenData::As, "as")?;
+                let name = match self.parse_identifier()?.data {
+                    ExprData::Identifier(s) => s,
+                    _ => unreachable!(),
+                };
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Ok(Decl::Import(path, name, line))
+            }
+
+            // 'export' ( varDecl | constDecl | classDecl | traitDecl )
+            Export => {
+                self.next();
+
+                if !matches!(self.peek().data, Var | Const | Class | Trait)
+                    && !self.is_function_declaration()
+                {
+                    return Err(Error::parse_error(
+                        "only variable, function, class and trait declarations can be exported"
+                            .into(),
+                        line,
+                    ));
+                }
+
+                Ok(Decl::Export(self.declaration()?.into()))
+            }
+
+            _ => self.declaration(),
+        }
+    }
+
     fn declaration(&mut self) -> Result<Decl, Error> {
         let decl = match &self.peek().data {
             Var => {
@@ -124,487 +220,3475 @@ impl Parser {
                 // todo: allow chained equals
                 self.expect(TokenData::Equal, "equal")?;
 
-                let expr = self.parse_expression()?;
+                let expr = Self::name_function(self.initializer()?, &id);
+
+                self.expect(TokenData::Semicolon, "semicolon")?;
 
                 Decl::VarDecl(id, expr)
             }
 
+            Const => {
+                self.next();
+
+                let id = self.parse_identifier()?;
+                self.expect(TokenData::Equal, "equal")?;
+                let expr = Self::name_function(self.initializer()?, &id);
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Decl::ConstDecl(id, expr)
+            }
+
+            Fun if self.is_function_declaration() => self.function_declaration()?,
+            Class => self.class_declaration()?,
+            Trait => self.trait_declaration()?,
+
             _ => {
                 let inner = self.statement()?;
                 Decl::Stmt(inner)
             }
         };
 
-        self.expect(TokenData::Semicolon, "semicolon")?;
-
         Ok(decl)
     }
 
-    fn statement(&mut self) -> Result<Stmt, Error> {
-        let stmt = match self.peek().data {
-            // 'print' expr ;
-            Print => {
-                self.next();
-
-                let inner = self.parse_expression()?;
-                Stmt::Print(inner)
+    // Whether the current `fun` starts a named declaration, rather than an anonymous function in
+    // an expression statement.
+    fn is_function_declaration(&self) -> bool {
+        self.peek().data == Fun
+            && match self.peek_at(1).data {
+                Identifier(_) => true,
+                Star => matches!(self.peek_at(2).data, Identifier(_)),
+                _ => false,
             }
+    }
 
-            // bare expression ;
-            _ => {
-                let inner = self.parse_expression()?;
-                Stmt::Expr(inner)
-            }
-        };
+    // 'fun' '*'? IDENTIFIER '(' parameters? ')' block, which is sugar for
+    // `var IDENTIFIER = fun ...;`
+    fn function_declaration(&mut self) -> Result<Decl, Error> {
+        self.next();
+        let generator = self.peek().data == Star;
+        if generator {
+            self.next();
+        }
 
-        Ok(stmt)
-    }
+        let id = self.parse_identifier()?;
+        let lambda = self.lambda(false, generator)?;
 
-    fn parse_expression(&mut self) -> Result<Expr, Error> {
-        self.equality()
+        Ok(Decl::VarDecl(id.clone(), Self::name_function(lambda, &id)))
     }
 
-    fn equality(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.comparison()?;
-        if self.is_at_end() {
-            return Ok(expr);
-        }
-
-        loop {
-            expr = recurse_binary_expr!(
-                self,
-                expr,
-                self.comparison()?,
-                (TokenData::BangEqual, BinOp::Neq),
-                (TokenData::EqualEqual, BinOp::Eq),
-            );
+    // Names an anonymous function after the variable it's declared as, for error messages.
+    fn name_function(mut expr: Expr, id: &Expr) -> Expr {
+        if let (ExprData::Lambda(decl), ExprData::Identifier(name)) = (&mut expr.data, &id.data) {
+            Rc::make_mut(decl).name.get_or_insert_with(|| name.clone());
         }
 
-        Ok(expr)
+        expr
     }
 
-    fn comparison(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.term()?;
-        if self.is_at_end() {
-            return Ok(expr);
-        }
+    // 'class' IDENTIFIER ( '<' IDENTIFIER )? ( 'with' IDENTIFIER ( ',' IDENTIFIER )* )? '{' member* '}'
+    fn class_declaration(&mut self) -> Result<Decl, Error> {
+        let line = self.peek().line;
+        self.next();
 
-        loop {
-            expr = recurse_binary_expr!(
-                self,
-                expr,
-                self.term()?,
-                (TokenData::Greater, BinOp::Gt),
-                (TokenData::GreaterEqual, BinOp::GtEq),
-                (TokenData::Less, BinOp::Lt),
-                (TokenData::LessEqual, BinOp::LtEq),
-            );
+        let name = match self.parse_identifier()?.data {
+            ExprData::Identifier(s) => s,
+            _ => unreachable!(),
+        };
+        let superclass = if self.peek().data == Less {
+            self.next();
+            Some(self.parse_identifier()?)
+        } else {
+            None
+        };
+        let mut traits = vec![];
+        if self.peek().data == With {
+            self.next();
+            traits.push(self.parse_identifier()?);
+            while self.peek().data == Comma {
+                self.next();
+                traits.push(self.parse_identifier()?);
+            }
         }
+        self.expect(TokenData::LeftBrace, "opening brace")?;
 
-        Ok(expr)
+        let kind = match superclass {
+            Some(_) => ClassKind::Subclass,
+            None => ClassKind::Class,
+        };
+        let methods = self.class_body(kind)?;
+
+        Ok(Decl::Class(
+            ClassDecl {
+                name,
+                superclass,
+                traits,
+                methods,
+                line,
+            }
+            .into(),
+        ))
     }
 
-    fn term(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.factor()?;
-        if self.is_at_end() {
-            return Ok(expr);
-        }
+    // 'trait' IDENTIFIER '{' member* '}'
+    fn trait_declaration(&mut self) -> Result<Decl, Error> {
+        let line = self.peek().line;
+        self.next();
 
-        loop {
-            expr = recurse_binary_expr!(
-                self,
-                expr,
-                self.factor()?,
-                (TokenData::Plus, BinOp::Add),
-                (TokenData::Minus, BinOp::Sub),
-            );
-        }
+        let name = match self.parse_identifier()?.data {
+            ExprData::Identifier(s) => s,
+            _ => unreachable!(),
+        };
+        self.expect(TokenData::LeftBrace, "opening brace")?;
+        let methods = self.class_body(ClassKind::Trait)?;
+
+        Ok(Decl::Trait(
+            TraitDecl {
+                name,
+                methods,
+                line,
+            }
+            .into(),
+        ))
+    }
 
-        Ok(expr)
+    // The members of a class or trait body and its closing brace.
+    fn class_body(&mut self, kind: ClassKind) -> Result<Vec<Method>, Error> {
+        self.classes.push(kind);
+        let methods = self.class_members();
+        self.classes.pop();
+        let methods = methods?;
+
+        self.expect(TokenData::RightBrace, "closing brace")?;
+
+        Ok(methods)
     }
 
-    fn factor(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.unary()?;
-        if self.is_at_end() {
-            return Ok(expr);
+    // The members of a class body, up to but not including the closing brace.
+    fn class_members(&mut self) -> Result<Vec<Method>, Error> {
+        let mut methods: Vec<Method> = vec![];
+        while self.peek().data != RightBrace && !self.is_at_end() {
+            let line = self.peek().line;
+            let method = self.method()?;
+
+            if methods
+                .iter()
+                .any(|m| m.kind == method.kind && m.name == method.name)
+            {
+                return Err(Error::parse_error(
+                    format!("{} is defined twice", method.name),
+                    line,
+                ));
+            }
+            methods.push(method);
         }
 
-        loop {
-            expr = recurse_binary_expr!(
-                self,
-                expr,
-                self.unary()?,
-                (TokenData::Slash, BinOp::Div),
-                (TokenData::Star, BinOp::Mult),
-            );
+        Ok(methods)
+    }
+
+    // 'class'? IDENTIFIER parameters block | IDENTIFIER block | 'set' IDENTIFIER parameters block
+    fn method(&mut self) -> Result<Method, Error> {
+        let mut kind = match (&self.peek().data, &self.peek_at(1).data) {
+            (Class, _) => MethodKind::Static,
+            // `set` is only a keyword here, so a method can still be called set
+            (Identifier(s), Identifier(_)) if s == "set" => MethodKind::Setter,
+            _ => MethodKind::Method,
+        };
+        if kind != MethodKind::Method {
+            self.next();
         }
 
-        Ok(expr)
-    }
+        let line = self.peek().line;
+        let name = match self.parse_identifier()?.data {
+            ExprData::Identifier(s) => s,
+            _ => unreachable!(),
+        };
 
-    fn unary(&mut self) -> Result<Expr, Error> {
-        let Token { data, line } = self.peek();
-        let line = *line;
+        let (params, rest) = if kind == MethodKind::Method && self.peek().data == LeftBrace {
+            kind = MethodKind::Getter;
+            (vec![], None)
+        } else {
+            self.parameters()?
+        };
+        if kind == MethodKind::Setter && (params.len() != 1 || rest.is_some()) {
+            return Err(Error::parse_error(
+                format!("setter {name} must have exactly one parameter"),
+                line,
+            ));
+        }
 
-        let expr = match &data {
-            Minus => {
-                self.next();
-                let e = self.unary()?;
-                Expr::new(ExprData::Unary(UnaryOp::Negative, e.into()), line)
+        let initializer = kind == MethodKind::Method && name == "init";
+        let body = self.function_body(false, initializer, false)?;
+
+        Ok(Method {
+            name: name.clone(),
+            kind,
+            decl: FunctionDecl {
+                name: Some(name),
+                params,
+                rest,
+                body,
+                generator: false,
             }
-            Bang => {
+            .into(),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, Error> {
+        let stmt = match self.peek().data {
+            // 'print' expr ;
+            Print => {
                 self.next();
-                let e = self.unary()?;
-                Expr::new(ExprData::Unary(UnaryOp::Inverse, e.into()), line)
-            }
-            _ => self.primary()?,
-        };
 
-        Ok(expr)
-    }
+                let inner = self.parse_expression()?;
+                self.expect(TokenData::Semicolon, "semicolon")?;
 
-    fn parse_identifier(&mut self) -> Result<Expr, Error> {
-        let Token { data, line } = self.peek();
-        let ident = match &data {
-            Identifier(s) => {
-                // clone the string out of the immutable borrow before modifying self
-                let expr = Expr::new(ExprData::Identifier(s.clone()), *line);
+                Stmt::Print(inner)
+            }
 
+            // 'return' expr? ;
+            Return => {
+                let line = self.peek().line;
+                if self.function_depth == 0 {
+                    return Err(Error::parse_error(
+                        "can't return from top-level code".into(),
+                        line,
+                    ));
+                }
                 self.next();
 
-                expr
-            }
-            _ => {
-                return Err(Error::parse_error(
-                    "expected valid identifier".into(),
-                    *line,
-                ));
+                let inner = if self.peek().data == Semicolon {
+                    None
+                } else if self.in_initializer {
+                    return Err(Error::parse_error(
+                        "can't return a value from an initializer".into(),
+                        line,
+                    ));
+                } else {
+                    Some(self.parse_expression()?)
+                };
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Stmt::Return(inner)
             }
-        };
 
-        Ok(ident)
-    }
+            // 'if' ( expr ) stmt ( 'else' stmt )?
+            If => {
+                self.next();
 
-    fn primary(&mut self) -> Result<Expr, Error> {
-        let Token { data, line } = self.peek();
-        let ident = match &data {
-            Identifier(s) => {
-                // clone the string out of the immutable borrow before modifying self
-                let expr = Expr::new(ExprData::Identifier(s.clone()), *line);
+                self.expect(TokenData::LeftParen, "opening parens")?;
+                let cond = self.parse_expression()?;
+                self.expect(TokenData::RightParen, "closing parens")?;
 
-                self.next();
+                let then_branch = self.statement()?;
+                let else_branch = if self.peek().data == Else {
+                    self.next();
+                    Some(self.statement()?.into())
+                } else {
+                    None
+                };
 
-                expr
+                Stmt::If(cond, then_branch.into(), else_branch)
             }
-            StringToken(s) => {
-                // clone the string out of the immutable borrow before modifying self
-                let expr = Expr::new(ExprData::StringLiteral(s.clone()), *line);
 
+            // 'while' ( expr ) stmt
+            While => {
                 self.next();
 
-                expr
+                self.expect(TokenData::LeftParen, "opening parens")?;
+                let cond = self.parse_expression()?;
+                self.expect(TokenData::RightParen, "closing parens")?;
+
+                let body = self.loop_body()?;
+
+                Stmt::While(cond, body.into())
             }
-            Number(n) => {
-                // copy the literal out of the immutable borrow before modifying self
-                let expr = Expr::new(ExprData::NumberLiteral(*n), *line);
 
+            // 'for' ( IDENTIFIER 'in' expr ) stmt
+            For if self.peek_at(3).data == In => self.for_in_statement()?,
+
+            // 'for' ( init? ; cond? ; incr? ) stmt
+            For => {
                 self.next();
+                self.expect(TokenData::LeftParen, "opening parens")?;
 
-                expr
+                let init = match self.peek().data {
+                    Semicolon => {
+                        self.next();
+                        None
+                    }
+                    // the generator can't be suspended before the loop starts
+                    Var | Const => Some(self.without_yield(Self::declaration)?.into()),
+                    _ => {
+                        let e = self.parse_expression()?;
+                        self.expect(TokenData::Semicolon, "semicolon")?;
+                        Some(Decl::Stmt(Stmt::Expr(e)).into())
+                    }
+                };
+
+                let cond = match self.peek().data {
+                    Semicolon => None,
+                    _ => Some(self.parse_expression()?),
+                };
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                let incr = match self.peek().data {
+                    RightParen => None,
+                    _ => Some(self.parse_expression()?),
+                };
+                self.expect(TokenData::RightParen, "closing parens")?;
+
+                let body = self.loop_body()?;
+
+                Stmt::For(init, cond, incr, body.into())
             }
-            True => {
-                let expr = Expr::new(ExprData::True, *line);
 
+            // 'break' ; | 'continue' ;
+            Break | Continue => {
+                let Token { data, line } = self.peek();
+                let (stmt, keyword) = match data {
+                    Break => (Stmt::Break, "break"),
+                    _ => (Stmt::Continue, "continue"),
+                };
+
+                if self.loop_depth == 0 {
+                    return Err(Error::parse_error(
+                        format!("can't {keyword} outside of a loop"),
+                        *line,
+                    ));
+                }
                 self.next();
+                self.expect(TokenData::Semicolon, "semicolon")?;
 
-                expr
+                stmt
             }
-            False => {
-                let expr = Expr::new(ExprData::False, *line);
 
+            // 'throw' expr ;
+            Throw => {
                 self.next();
 
-                expr
+                let inner = self.parse_expression()?;
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Stmt::Throw(inner)
             }
-            Nil => {
-                let expr = Expr::new(ExprData::Nil, *line);
 
-                self.next();
+            // A generator can't be suspended inside these.
+            Try => self.without_yield(Self::try_statement)?,
+            Match => self.without_yield(Self::match_statement)?,
 
-                expr
+            // 'yield' expr? ;
+            Yield => {
+                let inner = self.yield_expression()?;
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Stmt::Expr(inner)
             }
-            LeftParen => {
-                self.next(); // first move pointer past LeftParen
 
-                let expr = self.equality()?;
+            // IDENTIFIER = 'yield' expr? ;
+            Identifier(_) if self.peek_at(1).data == Equal && self.peek_at(2).data == Yield => {
+                let line = self.peek_at(1).line;
+                let name = self.parameter_name()?;
+                self.next();
 
-                self.expect(TokenData::RightParen, "closing parens")?;
+                let value = self.yield_expression()?;
+                self.expect(TokenData::Semicolon, "semicolon")?;
 
-                expr
+                Stmt::Expr(Expr::new(ExprData::Assign(name, value.into()), line))
             }
-            Eof => {
+
+            Import | Export => {
                 return Err(Error::parse_error(
-                    "unexpected end of file".to_string(),
-                    *line,
+                    "imports and exports are only allowed at the top level".into(),
+                    self.peek().line,
                 ))
             }
-            t => {
-                return Err(Error::parse_error(
-                    format!("unexpected token: {t:?}"),
-                    *line,
-                ))
+
+            // A `{` at the start of a statement is always a block, like in JS. Map literals in
+            // statement position need to be wrapped in parentheses.
+            LeftBrace => Stmt::Block(self.block()?),
+
+            // bare expression ;
+            _ => {
+                let inner = self.parse_expression()?;
+                self.expect(TokenData::Semicolon, "semicolon")?;
+
+                Stmt::Expr(inner)
             }
         };
 
-        Ok(ident)
+        Ok(stmt)
     }
-}
 
-#[cfg(test)]
+    // 'try' block ( 'catch' ( IDENTIFIER ) block )? ( 'finally' block )?
+    fn try_statement(&mut self) -> Result<Stmt, Error> {
+        let line = self.peek().line;
+        self.next();
+
+        let body = self.block()?;
+
+        let catch = if self.peek().data == Catch {
+            self.next();
+
+            self.expect(TokenData::LeftParen, "opening parens")?;
+            let name = match self.parse_identifier()?.data {
+                ExprData::Identifier(s) => s,
+                _ => unreachable!(),
+            };
+            self.expect(TokenData::RightParen, "closing parens")?;
+
+            Some((name, self.block()?))
+        } else {
+            None
+        };
+
+        let finally = if self.peek().data == Finally {
+            self.next();
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(Error::parse_error(
+                "expected catch or finally after try block".into(),
+                line,
+            ));
+        }
+
+        Ok(Stmt::Try(body, catch, finally))
+    }
+
+    // Parses something that can't contain a yield, even in a generator.
+    fn without_yield<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let can_yield = std::mem::take(&mut self.can_yield);
+        let parsed = f(self);
+        self.can_yield = can_yield;
+
+        parsed
+    }
+
+    // 'yield' expr?, which takes the rest of the statement like `return` does. The callers only
+    // allow it where a generator can be suspended: as a statement, an initializer, or the value of
+    // an assignment statement.
+    fn yield_expression(&mut self) -> Result<Expr, Error> {
+        let line = self.peek().line;
+        if !self.can_yield {
+            return Err(Error::parse_error(
+                "can only yield in a generator, outside of try, match and for loop initializers"
+                    .into(),
+                line,
+            ));
+        }
+        self.next();
+
+        let inner = if self.peek().data == Semicolon {
+            None
+        } else {
+            Some(self.parse_expression()?.into())
+        };
+
+        Ok(Expr::new(ExprData::Yield(inner), line))
+    }
+
+    // A var or const initializer, which can be a yield.
+    fn initializer(&mut self) -> Result<Expr, Error> {
+        if self.peek().data == Yield {
+            self.yield_expression()
+        } else {
+            self.assignment()
+        }
+    }
+
+    // 'match' expr '{' ( pattern ( 'if' expr )? '=>' stmt ','? )* '}'
+    fn match_statement(&mut self) -> Result<Stmt, Error> {
+        self.next();
+
+        let subject = self.parse_expression()?;
+        self.expect(TokenData::LeftBrace, "opening brace")?;
+
+        let mut arms = vec![];
+        while self.peek().data != RightBrace && !self.is_at_end() {
+            let line = self.peek().line;
+
+            let pattern = self.pattern()?;
+            let guard = if self.peek().data == If {
+                self.next();
+                self.in_guard = true;
+                let guard = self.parse_expression();
+                self.in_guard = false;
+                Some(guard?)
+            } else {
+                None
+            };
+            self.expect(TokenData::Arrow, "=>")?;
+            let body = self.statement()?;
+
+            if self.peek().data == Comma {
+                self.next();
+            }
+
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+                line,
+            });
+        }
+
+        self.expect(TokenData::RightBrace, "closing brace")?;
+
+        Ok(Stmt::Match(subject, arms))
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, Error> {
+        let mut alternatives = vec![self.pattern_alternative()?];
+        while self.peek().data == Pipe {
+            self.next();
+            alternatives.push(self.pattern_alternative()?);
+        }
+
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Pattern::Or(alternatives))
+        }
+    }
+
+    // The `field: pattern` pairs of an instance pattern, up to and including the closing brace. A
+    // field on its own binds it to its name.
+    fn field_patterns(&mut self) -> Result<Vec<(String, Pattern)>, Error> {
+        let mut fields: Vec<(String, Pattern)> = vec![];
+        while self.peek().data != RightBrace {
+            let line = self.peek().line;
+            let name = self.parameter_name()?;
+            if fields.iter().any(|(f, _)| *f == name) {
+                return Err(Error::parse_error(
+                    format!("field {name} appears more than once in pattern"),
+                    line,
+                ));
+            }
+
+            let pattern = if self.peek().data == Colon {
+                self.next();
+                self.pattern()?
+            } else {
+                Pattern::Binding(name.clone())
+            };
+            fields.push((name, pattern));
+
+            if self.peek().data != Comma {
+                break;
+            }
+            self.next();
+        }
+        self.expect(TokenData::RightBrace, "closing brace")?;
+
+        Ok(fields)
+    }
+
+    fn pattern_alternative(&mut self) -> Result<Pattern, Error> {
+        let Token { data, line } = self.peek().clone();
+        self.next();
+
+        let pattern = match data {
+            Identifier(s) if s == "_" => Pattern::Wildcard,
+            Identifier(s) if self.peek().data == LeftBrace => {
+                self.next();
+                Pattern::Instance(s, self.field_patterns()?)
+            }
+            Identifier(s) => Pattern::Binding(s),
+
+            Integer(n) => Pattern::Literal(Value::Int(n)),
+            BigInteger(n) => Pattern::Literal(Value::BigInt(n)),
+            Number(n) => Pattern::Literal(Value::Number(n)),
+            StringToken(s) => Pattern::Literal(Value::String(s)),
+            True => Pattern::Literal(Value::Boolean(true)),
+            False => Pattern::Literal(Value::Boolean(false)),
+            Nil => Pattern::Literal(Value::Nil),
+
+            // negative number literals
+            Minus => {
+                let Token { data, line } = self.peek().clone();
+                self.next();
+
+                match data {
+                    Integer(n) => Pattern::Literal(Value::Int(-n)),
+                    // -2^63 is the one BigInteger literal whose negation fits in an i64
+                    BigInteger(n) => Pattern::Literal(match n.neg().to_i64() {
+                        Some(n) => Value::Int(n),
+                        None => Value::BigInt(n.neg()),
+                    }),
+                    Number(n) => Pattern::Literal(Value::Number(-n)),
+                    _ => {
+                        return Err(Error::parse_error(
+                            format!("expected a number after - in pattern, got {data:?}"),
+                            line,
+                        ))
+                    }
+                }
+            }
+
+            LeftBracket => {
+                let mut elements = vec![];
+                if self.peek().data != RightBracket {
+                    loop {
+                        elements.push(self.pattern()?);
+
+                        if self.peek().data != Comma {
+                            break;
+                        }
+                        self.next();
+                    }
+                }
+                self.expect(TokenData::RightBracket, "closing bracket")?;
+
+                Pattern::List(elements)
+            }
+
+            _ => {
+                return Err(Error::parse_error(
+                    format!("expected a pattern, got {data:?}"),
+                    line,
+                ))
+            }
+        };
+
+        Ok(pattern)
+    }
+
+    fn for_in_statement(&mut self) -> Result<Stmt, Error> {
+        self.next();
+        self.expect(TokenData::LeftParen, "opening parens")?;
+
+        let name = match &self.peek().data {
+            Identifier(name) => name.clone(),
+            t => {
+                return Err(Error::parse_error(
+                    format!("expected loop variable, got {t:?}"),
+                    self.peek().line,
+                ))
+            }
+        };
+        self.next();
+        self.expect(TokenData::In, "in")?;
+
+        let iterable = self.parse_expression()?;
+        self.expect(TokenData::RightParen, "closing parens")?;
+
+        let body = self.loop_body()?;
+
+        Ok(Stmt::ForIn(name, iterable, body.into()))
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt, Error> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+
+        body
+    }
+
+    // '{' declaration* '}'
+    fn block(&mut self) -> Result<Vec<Decl>, Error> {
+        self.expect(TokenData::LeftBrace, "opening brace")?;
+
+        let mut decls = vec![];
+        while self.peek().data != RightBrace && !self.is_at_end() {
+            decls.push(self.declaration()?);
+        }
+
+        self.expect(TokenData::RightBrace, "closing brace")?;
+
+        Ok(decls)
+    }
+
+    // The parameter list of a function, including the parentheses.
+    // The parameters, and the rest parameter if there is one.
+    fn parameters(&mut self) -> Result<(Vec<Param>, Option<String>), Error> {
+        self.expect(TokenData::LeftParen, "opening parens")?;
+
+        let mut params: Vec<Param> = vec![];
+        let mut rest = None;
+        if self.peek().data != RightParen {
+            loop {
+                if self.peek().data == Ellipsis {
+                    self.next();
+                    rest = Some(self.parameter_name()?);
+                    break;
+                }
+
+                let line = self.peek().line;
+                let name = self.parameter_name()?;
+                let default = if self.peek().data == Equal {
+                    self.next();
+                    Some(self.assignment()?)
+                } else if params.last().is_some_and(|p| p.default.is_some()) {
+                    return Err(Error::parse_error(
+                        format!(
+                            "parameter {name} needs a default, since the one before it has one"
+                        ),
+                        line,
+                    ));
+                } else {
+                    None
+                };
+                params.push(Param { name, default });
+
+                if self.peek().data != Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+
+        if rest.is_some() && self.peek().data == Comma {
+            return Err(Error::parse_error(
+                "the rest parameter must be the last one".into(),
+                self.peek().line,
+            ));
+        }
+        self.expect(TokenData::RightParen, "closing parens")?;
+
+        Ok((params, rest))
+    }
+
+    fn parameter_name(&mut self) -> Result<String, Error> {
+        match self.parse_identifier()?.data {
+            ExprData::Identifier(s) => Ok(s),
+            _ => unreachable!(),
+        }
+    }
+
+    // Whether the parenthesized list starting at the current token is followed by `=>`, making it
+    // the parameter list of an arrow function rather than a grouping. This gives up as soon as the
+    // list stops looking like parameters, so most groupings are rejected within a token or two;
+    // only default values, which can be any expression, are skipped over to the matching bracket.
+    fn is_arrow_function(&self) -> bool {
+        let mut offset = 1;
+        if self.peek_at(offset).data != RightParen {
+            loop {
+                if self.peek_at(offset).data == Ellipsis {
+                    offset += 1;
+                }
+                if !matches!(self.peek_at(offset).data, Identifier(_)) {
+                    return false;
+                }
+                offset += 1;
+
+                if self.peek_at(offset).data == Equal {
+                    let mut depth = 0;
+                    loop {
+                        offset += 1;
+                        match self.peek_at(offset).data {
+                            LeftParen | LeftBracket | LeftBrace => depth += 1,
+                            Comma | RightParen if depth == 0 => break,
+                            RightParen | RightBracket | RightBrace => depth -= 1,
+                            Eof => return false,
+                            _ => (),
+                        }
+                    }
+                }
+
+                match self.peek_at(offset).data {
+                    Comma => offset += 1,
+                    RightParen => break,
+                    _ => return false,
+                }
+            }
+        }
+
+        self.peek_at(offset + 1).data == Arrow
+    }
+
+    // Parses an anonymous function, starting from its parameters. Arrow functions may have a
+    // single expression for a body, which is implicitly returned. Only `fun*` functions are
+    // generators.
+    fn lambda(&mut self, arrow: bool, generator: bool) -> Result<Expr, Error> {
+        let line = self.peek().line;
+
+        let (params, rest) = self.parameters()?;
+        if arrow {
+            self.expect(TokenData::Arrow, "=>")?;
+        }
+        let body = self.function_body(arrow, false, generator)?;
+
+        let decl = FunctionDecl {
+            name: None,
+            params,
+            rest,
+            body,
+            generator,
+        };
+
+        Ok(Expr::new(ExprData::Lambda(decl.into()), line))
+    }
+
+    // The body of a function or method, after its parameters. Loops outside the function don't
+    // count for `break` and `continue` inside it, and a yield inside it is only allowed if it's a
+    // generator itself.
+    fn function_body(
+        &mut self,
+        arrow: bool,
+        initializer: bool,
+        generator: bool,
+    ) -> Result<FunctionBody, Error> {
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let in_initializer = std::mem::replace(&mut self.in_initializer, initializer);
+        let can_yield = std::mem::replace(&mut self.can_yield, generator);
+        let in_guard = std::mem::take(&mut self.in_guard);
+        self.function_depth += 1;
+        let body = if self.peek().data == LeftBrace || !arrow {
+            self.block().map(FunctionBody::Block)
+        } else {
+            self.assignment().map(FunctionBody::Expr)
+        };
+        self.function_depth -= 1;
+        self.in_guard = in_guard;
+        self.can_yield = can_yield;
+        self.in_initializer = in_initializer;
+        self.loop_depth = loop_depth;
+
+        body
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, Error> {
+        self.comma()
+    }
+
+    fn comma(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.assignment()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.assignment()?,
+                (TokenData::Comma, BinOp::Comma),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    // Parses the target as an ordinary expression, then checks it is assignable once we see the
+    // `=`.
+    fn assignment(&mut self) -> Result<Expr, Error> {
+        let expr = self.ternary()?;
+
+        let Token { data, line } = self.peek();
+        let line = *line;
+
+        let op = match data {
+            Equal => None,
+            PlusEqual => Some(BinOp::Add),
+            MinusEqual => Some(BinOp::Sub),
+            StarEqual => Some(BinOp::Mult),
+            SlashEqual => Some(BinOp::Div),
+            _ => return Ok(expr),
+        };
+        self.next();
+
+        let value = self.assignment()?;
+
+        if let Some(op) = op {
+            let target = Self::update_target(expr, line)?;
+            return Ok(Expr::new(
+                ExprData::CompoundAssign(op, target.into(), value.into()),
+                line,
+            ));
+        }
+
+        match expr.data {
+            ExprData::Identifier(id) => Ok(Expr::new(ExprData::Assign(id, value.into()), line)),
+            ExprData::Index(target, index) => Ok(Expr::new(
+                ExprData::IndexSet(target, index, value.into()),
+                line,
+            )),
+            ExprData::Get(object, name) => {
+                Ok(Expr::new(ExprData::Set(object, name, value.into()), line))
+            }
+            _ => Err(Error::parse_error("invalid assignment target".into(), line)),
+        }
+    }
+
+    // Compound assignment and ++/-- can update variables, indexed elements and properties.
+    fn update_target(expr: Expr, line: u32) -> Result<Expr, Error> {
+        match expr.data {
+            ExprData::Identifier(_) | ExprData::Index(..) | ExprData::Get(..) => Ok(expr),
+            _ => Err(Error::parse_error("invalid assignment target".into(), line)),
+        }
+    }
+
+    // Right-associative, since the else branch can itself be a ternary.
+    fn ternary(&mut self) -> Result<Expr, Error> {
+        let cond = self.equality()?;
+
+        let line = self.peek().line;
+        if self.peek().data != Question {
+            return Ok(cond);
+        }
+        self.next();
+
+        let then_expr = self.assignment()?;
+        self.expect(TokenData::Colon, "colon")?;
+        let else_expr = self.assignment()?;
+
+        Ok(Expr::new(
+            ExprData::Ternary(cond.into(), then_expr.into(), else_expr.into()),
+            line,
+        ))
+    }
+
+    fn equality(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.comparison()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.comparison()?,
+                (TokenData::BangEqual, BinOp::Neq),
+                (TokenData::EqualEqual, BinOp::Eq),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.range()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.range()?,
+                (TokenData::Greater, BinOp::Gt),
+                (TokenData::GreaterEqual, BinOp::GtEq),
+                (TokenData::Less, BinOp::Lt),
+                (TokenData::LessEqual, BinOp::LtEq),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    // Not associative: `a..b..c` is an error.
+    fn range(&mut self) -> Result<Expr, Error> {
+        let start = self.bit_or()?;
+
+        let line = self.peek().line;
+        if self.peek().data != DotDot {
+            return Ok(start);
+        }
+        self.next();
+
+        let end = self.bit_or()?;
+        Ok(Expr::new(
+            ExprData::Binary(BinOp::Range, start.into(), end.into()),
+            line,
+        ))
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.bit_xor()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr =
+                recurse_binary_expr!(self, expr, self.bit_xor()?, (TokenData::Pipe, BinOp::BitOr));
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.bit_and()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.bit_and()?,
+                (TokenData::Caret, BinOp::BitXor)
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.shift()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.shift()?,
+                (TokenData::Ampersand, BinOp::BitAnd)
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.term()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.term()?,
+                (TokenData::LessLess, BinOp::Shl),
+                (TokenData::GreaterGreater, BinOp::Shr),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.factor()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.factor()?,
+                (TokenData::Plus, BinOp::Add),
+                (TokenData::Minus, BinOp::Sub),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        loop {
+            expr = recurse_binary_expr!(
+                self,
+                expr,
+                self.unary()?,
+                (TokenData::Slash, BinOp::Div),
+                (TokenData::Star, BinOp::Mult),
+                (TokenData::Percent, BinOp::Mod),
+                (TokenData::TildeSlash, BinOp::FloorDiv),
+            );
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let Token { data, line } = self.peek();
+        let line = *line;
+
+        let expr = match &data {
+            Minus => {
+                self.next();
+                let e = self.unary()?;
+                Expr::new(ExprData::Unary(UnaryOp::Negative, e.into()), line)
+            }
+            Bang => {
+                self.next();
+                let e = self.unary()?;
+                Expr::new(ExprData::Unary(UnaryOp::Inverse, e.into()), line)
+            }
+            Tilde => {
+                self.next();
+                let e = self.unary()?;
+                Expr::new(ExprData::Unary(UnaryOp::BitNot, e.into()), line)
+            }
+            PlusPlus | MinusMinus => {
+                let op = if *data == PlusPlus {
+                    BinOp::Add
+                } else {
+                    BinOp::Sub
+                };
+                self.next();
+
+                let target = Self::update_target(self.unary()?, line)?;
+                let one = Expr::new(ExprData::IntegerLiteral(1), line);
+                Expr::new(
+                    ExprData::CompoundAssign(op, target.into(), one.into()),
+                    line,
+                )
+            }
+            _ => self.power()?,
+        };
+
+        Ok(expr)
+    }
+
+    fn power(&mut self) -> Result<Expr, Error> {
+        let expr = self.postfix()?;
+
+        let Token { data, line } = self.peek();
+        let line = *line;
+
+        if *data != StarStar {
+            return Ok(expr);
+        }
+        self.next();
+
+        let exponent = self.unary()?;
+        Ok(Expr::new(
+            ExprData::Binary(BinOp::Pow, expr.into(), exponent.into()),
+            line,
+        ))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let expr = self.call()?;
+
+        let Token { data, line } = self.peek();
+        let line = *line;
+
+        let op = match data {
+            PlusPlus => BinOp::Add,
+            MinusMinus => BinOp::Sub,
+            _ => return Ok(expr),
+        };
+        self.next();
+
+        let target = Self::update_target(expr, line)?;
+        Ok(Expr::new(ExprData::PostfixUpdate(op, target.into()), line))
+    }
+
+    // Calls and indexing chain left to right, e.g. `f(1)[0](2)`.
+    fn call(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.primary()?;
+
+        loop {
+            let Token { data, line } = self.peek();
+            let line = *line;
+
+            expr = match data {
+                LeftParen => {
+                    self.next();
+                    let (args, named) = self.bracketed(Self::call_arguments)?;
+                    self.expect(TokenData::RightParen, "closing parens")?;
+
+                    Expr::new(ExprData::Call(expr.into(), args, named), line)
+                }
+                LeftBracket => {
+                    self.next();
+                    let index = self.bracketed(Self::parse_expression)?;
+                    self.expect(TokenData::RightBracket, "closing bracket")?;
+
+                    Expr::new(ExprData::Index(expr.into(), index.into()), line)
+                }
+                Dot => {
+                    self.next();
+                    let name = match self.parse_identifier()?.data {
+                        ExprData::Identifier(s) => s,
+                        _ => unreachable!(),
+                    };
+
+                    Expr::new(ExprData::Get(expr.into(), name), line)
+                }
+                _ => break,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // The positional and named arguments of a call, up to but not including the closing parens.
+    fn call_arguments(&mut self) -> Result<CallArgs, Error> {
+        let mut args = vec![];
+        let mut named: Vec<(String, Expr)> = vec![];
+        if self.peek().data == RightParen {
+            return Ok((args, named));
+        }
+
+        loop {
+            let line = self.peek().line;
+            match (&self.peek().data, &self.peek_at(1).data) {
+                (Identifier(name), Colon) => {
+                    let name = name.clone();
+                    if named.iter().any(|(n, _)| *n == name) {
+                        return Err(Error::parse_error(
+                            format!("argument {name} is given more than once"),
+                            line,
+                        ));
+                    }
+
+                    self.next();
+                    self.next();
+                    named.push((name, self.assignment()?));
+                }
+                _ if !named.is_empty() => {
+                    return Err(Error::parse_error(
+                        "positional arguments must come before named ones".into(),
+                        line,
+                    ));
+                }
+                _ => args.push(self.assignment()?),
+            }
+
+            if self.peek().data != Comma {
+                break;
+            }
+            self.next();
+        }
+
+        Ok((args, named))
+    }
+
+    // Comma-separated expressions, up to but not including the closing token.
+    fn arguments(&mut self, closing: TokenData) -> Result<Vec<Expr>, Error> {
+        let mut args = vec![];
+        if self.peek().data == closing {
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.assignment()?);
+
+            if self.peek().data != Comma {
+                break;
+            }
+            self.next();
+        }
+
+        Ok(args)
+    }
+
+    // Parses what's inside a pair of brackets, where a `=>` can't be the end of a match guard.
+    fn bracketed<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let in_guard = std::mem::take(&mut self.in_guard);
+        let result = parse(self);
+        self.in_guard = in_guard;
+
+        result
+    }
+
+    fn parse_identifier(&mut self) -> Result<Expr, Error> {
+        let Token { data, line } = self.peek();
+        let ident = match &data {
+            Identifier(s) => {
+                // clone the string out of the immutable borrow before modifying self
+                let expr = Expr::new(ExprData::Identifier(s.clone()), *line);
+
+                self.next();
+
+                expr
+            }
+            _ => {
+                return Err(Error::parse_error(
+                    "expected valid identifier".into(),
+                    *line,
+                ));
+            }
+        };
+
+        Ok(ident)
+    }
+
+    // Comma-separated `key: value` pairs, up to but not including the closing brace.
+    fn map_entries(&mut self) -> Result<Vec<(Expr, Expr)>, Error> {
+        let mut entries = vec![];
+        if self.peek().data == RightBrace {
+            return Ok(entries);
+        }
+
+        loop {
+            let k = self.assignment()?;
+            self.expect(TokenData::Colon, "colon")?;
+            let v = self.assignment()?;
+            entries.push((k, v));
+
+            if self.peek().data != Comma {
+                break;
+            }
+            self.next();
+        }
+
+        Ok(entries)
+    }
+
+    // Desugars an interpolated string into concatenation, converting each embedded expression to a
+    // string:
+    //
+    //   "a ${b} c"  =>  "a " + to_string(b) + " c"
+    fn interpolation(&mut self) -> Result<Expr, Error> {
+        let line = self.peek().line;
+
+        let mut parts = vec![];
+        loop {
+            let Token { data, line } = self.peek();
+            let line = *line;
+
+            match data {
+                Interpolation(s) => {
+                    parts.push(Expr::new(ExprData::StringLiteral(s.clone()), line));
+                    self.next();
+
+                    let e = self.parse_expression()?;
+                    parts.push(Expr::new(
+                        ExprData::Unary(UnaryOp::ToString, e.into()),
+                        line,
+                    ));
+                }
+                StringToken(s) => {
+                    parts.push(Expr::new(ExprData::StringLiteral(s.clone()), line));
+                    self.next();
+                    break;
+                }
+                t => {
+                    return Err(Error::parse_error(
+                        format!("expected end of interpolated expression, got {t:?}"),
+                        line,
+                    ))
+                }
+            }
+        }
+
+        // Every part is a string, so empty literals can be dropped.
+        let expr = parts
+            .into_iter()
+            .filter(|e| e.data != ExprData::StringLiteral(String::new()))
+            .reduce(|acc, e| Expr::new(ExprData::Binary(BinOp::Add, acc.into(), e.into()), line))
+            .unwrap_or(Expr::new(ExprData::StringLiteral(String::new()), line));
+
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let Token { data, line } = self.peek();
+        let ident = match &data {
+            Identifier(s) => {
+                // clone the string out of the immutable borrow before modifying self
+                let expr = Expr::new(ExprData::Identifier(s.clone()), *line);
+
+                self.next();
+
+                expr
+            }
+            StringToken(s) => {
+                // clone the string out of the immutable borrow before modifying self
+                let expr = Expr::new(ExprData::StringLiteral(s.clone()), *line);
+
+                self.next();
+
+                expr
+            }
+            Interpolation(_) => self.interpolation()?,
+            Number(n) => {
+                // copy the literal out of the immutable borrow before modifying self
+                let expr = Expr::new(ExprData::NumberLiteral(*n), *line);
+
+                self.next();
+
+                expr
+            }
+            Integer(n) => {
+                let expr = Expr::new(ExprData::IntegerLiteral(*n), *line);
+
+                self.next();
+
+                expr
+            }
+            BigInteger(n) => {
+                let expr = Expr::new(ExprData::BigIntegerLiteral(n.clone()), *line);
+
+                self.next();
+
+                expr
+            }
+            True => {
+                let expr = Expr::new(ExprData::True, *line);
+
+                self.next();
+
+                expr
+            }
+            False => {
+                let expr = Expr::new(ExprData::False, *line);
+
+                self.next();
+
+                expr
+            }
+            Nil => {
+                let expr = Expr::new(ExprData::Nil, *line);
+
+                self.next();
+
+                expr
+            }
+            This => {
+                let line = *line;
+                if self.classes.is_empty() {
+                    return Err(Error::parse_error(
+                        "can't use this outside of a class".into(),
+                        line,
+                    ));
+                }
+                self.next();
+
+                Expr::new(ExprData::This, line)
+            }
+            Super => {
+                let line = *line;
+                match self.classes.last() {
+                    Some(ClassKind::Subclass) => (),
+                    Some(ClassKind::Trait) => {
+                        return Err(Error::parse_error(
+                            "can't use super in a trait".into(),
+                            line,
+                        ))
+                    }
+                    Some(ClassKind::Class) => {
+                        return Err(Error::parse_error(
+                            "can't use super in a class with no superclass".into(),
+                            line,
+                        ))
+                    }
+                    None => {
+                        return Err(Error::parse_error(
+                            "can't use super outside of a class".into(),
+                            line,
+                        ))
+                    }
+                }
+                self.next();
+
+                self.expect(TokenData::Dot, "dot after super")?;
+                let name = match self.parse_identifier()?.data {
+                    ExprData::Identifier(s) => s,
+                    _ => unreachable!(),
+                };
+
+                Expr::new(ExprData::Super(name), line)
+            }
+            Fun => {
+                self.next();
+                let generator = self.peek().data == Star;
+                if generator {
+                    self.next();
+                }
+                self.lambda(false, generator)?
+            }
+            LeftParen if !self.in_guard && self.is_arrow_function() => self.lambda(true, false)?,
+            LeftParen => {
+                self.next(); // first move pointer past LeftParen
+
+                let expr = self.bracketed(Self::parse_expression)?;
+
+                self.expect(TokenData::RightParen, "closing parens")?;
+
+                expr
+            }
+            LeftBracket => {
+                let line = *line;
+                self.next();
+
+                let elements = self.bracketed(|p| p.arguments(RightBracket))?;
+
+                self.expect(TokenData::RightBracket, "closing bracket")?;
+
+                Expr::new(ExprData::ListLiteral(elements), line)
+            }
+            LeftBrace => {
+                let line = *line;
+                self.next();
+
+                let entries = self.bracketed(Self::map_entries)?;
+
+                self.expect(TokenData::RightBrace, "closing brace")?;
+
+                Expr::new(ExprData::MapLiteral(entries), line)
+            }
+            // outside of a generator, this fails the same way a yield statement would
+            Yield if !self.can_yield => return self.yield_expression(),
+            Yield => {
+                return Err(Error::parse_error(
+                    "yield can only be a statement, an initializer or the value of an assignment \
+                     statement"
+                        .into(),
+                    *line,
+                ))
+            }
+            Eof => {
+                return Err(Error::parse_error(
+                    "unexpected end of file".to_string(),
+                    *line,
+                ))
+            }
+            t => {
+                return Err(Error::parse_error(
+                    format!("unexpected token: {t:?}"),
+                    *line,
+                ))
+            }
+        };
+
+        Ok(ident)
+    }
+}
+
+#[cfg(test)]
+// tokens! builds its Vec one push at a time
+#[allow(clippy::vec_init_then_push)]
 mod tests {
-    use crate::expr::{BinOp, Decl, Expr, ExprData, Stmt, UnaryOp};
+    use crate::eval::Value;
+    use crate::expr::{
+        BinOp, ClassDecl, Decl, Expr, ExprData, FunctionBody, FunctionDecl, MatchArm, Method,
+        MethodKind, Param, Pattern, Stmt, TraitDecl, UnaryOp,
+    };
     use crate::token::{Token, TokenData};
     use crate::tokens;
 
-    use super::parse;
+    use super::parse;
+
+    // ignores line numbers
+    macro_rules! assert_expr_parses {
+        ( $tokens:expr, $expected:expr ) => {{
+            let mut v = $tokens.clone();
+            // append a semicolon to create a valid program
+            v.push(Token::new(TokenData::Semicolon, 0));
+            v.push(Token::new(TokenData::Eof, 0));
+
+            let program = parse(v).unwrap();
+            assert_eq!(program[0], Decl::Stmt(Stmt::Expr($expected)));
+        }};
+    }
+
+    // wraps ExprData in Expr
+    macro_rules! e {
+        ( $e:expr ) => {{
+            Expr::new($e, 0)
+        }};
+    }
+
+    // a parameter without a default
+    fn param(name: &str) -> Param {
+        Param {
+            name: name.to_string(),
+            default: None,
+        }
+    }
+
+    #[test]
+    fn literals() {
+        assert_expr_parses!(tokens![TokenData::True], e!(ExprData::True));
+
+        assert_expr_parses!(tokens![TokenData::False], e!(ExprData::False));
+
+        assert_expr_parses!(tokens![TokenData::Nil], e!(ExprData::Nil));
+
+        assert_expr_parses!(
+            tokens![TokenData::Number(1.0)],
+            e!(ExprData::NumberLiteral(1.0))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::Integer(1)],
+            e!(ExprData::IntegerLiteral(1))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::StringToken("foo".to_string())],
+            e!(ExprData::StringLiteral("foo".to_string()))
+        );
+    }
+
+    #[test]
+    fn unary() {
+        assert_expr_parses!(
+            tokens![TokenData::Bang, TokenData::False],
+            e!(ExprData::Unary(
+                UnaryOp::Inverse,
+                e!(ExprData::False).into(),
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::Minus, TokenData::False],
+            e!(ExprData::Unary(
+                UnaryOp::Negative,
+                e!(ExprData::False).into()
+            ))
+        );
+    }
+
+    #[test]
+    fn cmps() {
+        assert_expr_parses!(
+            tokens![TokenData::True, TokenData::Greater, TokenData::False,],
+            e!(ExprData::Binary(
+                BinOp::Gt,
+                e!(ExprData::True).into(),
+                e!(ExprData::False).into()
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::True, TokenData::GreaterEqual, TokenData::False,],
+            e!(ExprData::Binary(
+                BinOp::GtEq,
+                e!(ExprData::True).into(),
+                e!(ExprData::False).into()
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::True, TokenData::Less, TokenData::False,],
+            e!(ExprData::Binary(
+                BinOp::Lt,
+                e!(ExprData::True).into(),
+                e!(ExprData::False).into()
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![TokenData::True, TokenData::LessEqual, TokenData::False,],
+            e!(ExprData::Binary(
+                BinOp::LtEq,
+                e!(ExprData::True).into(),
+                e!(ExprData::False).into()
+            ))
+        );
+
+        // left-associativity
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::LessEqual,
+                TokenData::Number(2.0),
+                TokenData::GreaterEqual,
+                TokenData::Number(3.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::GtEq,
+                e!(ExprData::Binary(
+                    BinOp::LtEq,
+                    e!(ExprData::NumberLiteral(1.0)).into(),
+                    e!(ExprData::NumberLiteral(2.0)).into(),
+                ))
+                .into(),
+                e!(ExprData::NumberLiteral(3.0)).into()
+            ))
+        );
+    }
+
+    #[test]
+    fn math() {
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Plus,
+                TokenData::Number(2.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Add,
+                e!(ExprData::NumberLiteral(1.0)).into(),
+                e!(ExprData::NumberLiteral(2.0)).into(),
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Minus,
+                TokenData::Number(2.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Sub,
+                e!(ExprData::NumberLiteral(1.0)).into(),
+                e!(ExprData::NumberLiteral(2.0)).into(),
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Slash,
+                TokenData::Number(2.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Div,
+                e!(ExprData::NumberLiteral(1.0)).into(),
+                e!(ExprData::NumberLiteral(2.0)).into(),
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Star,
+                TokenData::Number(2.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Mult,
+                e!(ExprData::NumberLiteral(1.0)).into(),
+                e!(ExprData::NumberLiteral(2.0)).into(),
+            ))
+        );
+
+        assert_expr_parses!(
+            tokens![
+                TokenData::Integer(1),
+                TokenData::Percent,
+                TokenData::Integer(2),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Mod,
+                e!(ExprData::IntegerLiteral(1)).into(),
+                e!(ExprData::IntegerLiteral(2)).into(),
+            ))
+        );
 
-    // ignores line numbers
-    macro_rules! assert_expr_parses {
-        ( $tokens:expr, $expected:expr ) => {{
-            let mut v = $tokens.clone();
-            // append a semicolon to create a valid program
-            v.push(Token::new(TokenData::Semicolon, 0));
-            v.push(Token::new(TokenData::Eof, 0));
+        // left-associative on same operator
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Star,
+                TokenData::Number(2.0),
+                TokenData::Star,
+                TokenData::Number(3.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Mult,
+                e!(ExprData::Binary(
+                    BinOp::Mult,
+                    e!(ExprData::NumberLiteral(1.0)).into(),
+                    e!(ExprData::NumberLiteral(2.0)).into(),
+                ))
+                .into(),
+                e!(ExprData::NumberLiteral(3.0)).into(),
+            ))
+        );
 
-            let program = parse(v).unwrap();
-            assert_eq!(program[0], Decl::Stmt(Stmt::Expr($expected)));
-        }};
+        // mult takes precedence over add
+        assert_expr_parses!(
+            tokens![
+                TokenData::Number(1.0),
+                TokenData::Plus,
+                TokenData::Number(2.0),
+                TokenData::Star,
+                TokenData::Number(3.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Add,
+                e!(ExprData::NumberLiteral(1.0)).into(),
+                e!(ExprData::Binary(
+                    BinOp::Mult,
+                    e!(ExprData::NumberLiteral(2.0)).into(),
+                    e!(ExprData::NumberLiteral(3.0)).into(),
+                ))
+                .into(),
+            ))
+        );
     }
 
-    // wraps ExprData in Expr
-    macro_rules! e {
-        ( $e:expr ) => {{
-            Expr::new($e, 0)
-        }};
+    #[test]
+    fn power() {
+        // right-associative: 2 ** 3 ** 2
+        assert_expr_parses!(
+            tokens![
+                TokenData::Integer(2),
+                TokenData::StarStar,
+                TokenData::Integer(3),
+                TokenData::StarStar,
+                TokenData::Integer(2),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Pow,
+                e!(ExprData::IntegerLiteral(2)).into(),
+                e!(ExprData::Binary(
+                    BinOp::Pow,
+                    e!(ExprData::IntegerLiteral(3)).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                ))
+                .into(),
+            ))
+        );
+
+        // binds tighter than unary minus on the left, but allows it on the right: -2 ** -1
+        assert_expr_parses!(
+            tokens![
+                TokenData::Minus,
+                TokenData::Integer(2),
+                TokenData::StarStar,
+                TokenData::Minus,
+                TokenData::Integer(1),
+            ],
+            e!(ExprData::Unary(
+                UnaryOp::Negative,
+                e!(ExprData::Binary(
+                    BinOp::Pow,
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                    e!(ExprData::Unary(
+                        UnaryOp::Negative,
+                        e!(ExprData::IntegerLiteral(1)).into(),
+                    ))
+                    .into(),
+                ))
+                .into(),
+            ))
+        );
+
+        // binds tighter than factor operators: 2 ~/ 3 ** 2
+        assert_expr_parses!(
+            tokens![
+                TokenData::Integer(2),
+                TokenData::TildeSlash,
+                TokenData::Integer(3),
+                TokenData::StarStar,
+                TokenData::Integer(2),
+            ],
+            e!(ExprData::Binary(
+                BinOp::FloorDiv,
+                e!(ExprData::IntegerLiteral(2)).into(),
+                e!(ExprData::Binary(
+                    BinOp::Pow,
+                    e!(ExprData::IntegerLiteral(3)).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                ))
+                .into(),
+            ))
+        );
     }
 
     #[test]
-    fn literals() {
-        assert_expr_parses!(tokens![TokenData::True], e!(ExprData::True));
+    fn bitwise() {
+        // each level binds tighter than the one before: 1 | 2 ^ 3 & 4 << 5
+        assert_expr_parses!(
+            tokens![
+                TokenData::Integer(1),
+                TokenData::Pipe,
+                TokenData::Integer(2),
+                TokenData::Caret,
+                TokenData::Integer(3),
+                TokenData::Ampersand,
+                TokenData::Integer(4),
+                TokenData::LessLess,
+                TokenData::Integer(5),
+            ],
+            e!(ExprData::Binary(
+                BinOp::BitOr,
+                e!(ExprData::IntegerLiteral(1)).into(),
+                e!(ExprData::Binary(
+                    BinOp::BitXor,
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                    e!(ExprData::Binary(
+                        BinOp::BitAnd,
+                        e!(ExprData::IntegerLiteral(3)).into(),
+                        e!(ExprData::Binary(
+                            BinOp::Shl,
+                            e!(ExprData::IntegerLiteral(4)).into(),
+                            e!(ExprData::IntegerLiteral(5)).into(),
+                        ))
+                        .into(),
+                    ))
+                    .into(),
+                ))
+                .into(),
+            ))
+        );
 
-        assert_expr_parses!(tokens![TokenData::False], e!(ExprData::False));
+        // bitwise binds tighter than comparison: x & 1 == ~0 >> 1
+        assert_expr_parses!(
+            tokens![
+                TokenData::Identifier("x".to_string()),
+                TokenData::Ampersand,
+                TokenData::Integer(1),
+                TokenData::EqualEqual,
+                TokenData::Tilde,
+                TokenData::Integer(0),
+                TokenData::GreaterGreater,
+                TokenData::Integer(1),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Eq,
+                e!(ExprData::Binary(
+                    BinOp::BitAnd,
+                    e!(ExprData::Identifier("x".to_string())).into(),
+                    e!(ExprData::IntegerLiteral(1)).into(),
+                ))
+                .into(),
+                e!(ExprData::Binary(
+                    BinOp::Shr,
+                    e!(ExprData::Unary(
+                        UnaryOp::BitNot,
+                        e!(ExprData::IntegerLiteral(0)).into(),
+                    ))
+                    .into(),
+                    e!(ExprData::IntegerLiteral(1)).into(),
+                ))
+                .into(),
+            ))
+        );
+    }
 
-        assert_expr_parses!(tokens![TokenData::Nil], e!(ExprData::Nil));
+    #[test]
+    fn grouping() {
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Number(1.0),
+                TokenData::Plus,
+                TokenData::Number(2.0),
+                TokenData::RightParen,
+                TokenData::Star,
+                TokenData::Number(3.0),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Mult,
+                e!(ExprData::Binary(
+                    BinOp::Add,
+                    e!(ExprData::NumberLiteral(1.0)).into(),
+                    e!(ExprData::NumberLiteral(2.0)).into(),
+                ))
+                .into(),
+                e!(ExprData::NumberLiteral(3.0)).into(),
+            ))
+        );
+    }
 
+    #[test]
+    fn lists() {
         assert_expr_parses!(
-            tokens![TokenData::Number(1.0)],
-            e!(ExprData::NumberLiteral(1.0))
+            tokens![TokenData::LeftBracket, TokenData::RightBracket],
+            e!(ExprData::ListLiteral(vec![]))
         );
 
         assert_expr_parses!(
-            tokens![TokenData::StringToken("foo".to_string())],
-            e!(ExprData::StringLiteral("foo".to_string()))
+            tokens![
+                TokenData::LeftBracket,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Integer(2),
+                TokenData::RightBracket,
+            ],
+            e!(ExprData::ListLiteral(vec![
+                e!(ExprData::IntegerLiteral(1)),
+                e!(ExprData::IntegerLiteral(2)),
+            ]))
+        );
+
+        // xs[0][1]
+        assert_expr_parses!(
+            tokens![
+                TokenData::Identifier("xs".to_string()),
+                TokenData::LeftBracket,
+                TokenData::Integer(0),
+                TokenData::RightBracket,
+                TokenData::LeftBracket,
+                TokenData::Integer(1),
+                TokenData::RightBracket,
+            ],
+            e!(ExprData::Index(
+                e!(ExprData::Index(
+                    e!(ExprData::Identifier("xs".to_string())).into(),
+                    e!(ExprData::IntegerLiteral(0)).into(),
+                ))
+                .into(),
+                e!(ExprData::IntegerLiteral(1)).into(),
+            ))
+        );
+
+        // xs[0] = 1 + 2
+        assert_expr_parses!(
+            tokens![
+                TokenData::Identifier("xs".to_string()),
+                TokenData::LeftBracket,
+                TokenData::Integer(0),
+                TokenData::RightBracket,
+                TokenData::Equal,
+                TokenData::Integer(1),
+                TokenData::Plus,
+                TokenData::Integer(2),
+            ],
+            e!(ExprData::IndexSet(
+                e!(ExprData::Identifier("xs".to_string())).into(),
+                e!(ExprData::IntegerLiteral(0)).into(),
+                e!(ExprData::Binary(
+                    BinOp::Add,
+                    e!(ExprData::IntegerLiteral(1)).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                ))
+                .into(),
+            ))
+        );
+
+        // only index expressions can be assigned to
+        let program = parse(tokens![
+            TokenData::Integer(1),
+            TokenData::Equal,
+            TokenData::Integer(2),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn calls() {
+        // f()(1, 2)
+        assert_expr_parses!(
+            tokens![
+                TokenData::Identifier("f".to_string()),
+                TokenData::LeftParen,
+                TokenData::RightParen,
+                TokenData::LeftParen,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Integer(2),
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Call(
+                e!(ExprData::Call(
+                    e!(ExprData::Identifier("f".to_string())).into(),
+                    vec![],
+                    vec![],
+                ))
+                .into(),
+                vec![
+                    e!(ExprData::IntegerLiteral(1)),
+                    e!(ExprData::IntegerLiteral(2)),
+                ],
+                vec![],
+            ))
+        );
+    }
+
+    #[test]
+    fn maps() {
+        // print {"a": 1, 2: nil}
+        let program = parse(tokens![
+            TokenData::Print,
+            TokenData::LeftBrace,
+            TokenData::StringToken("a".to_string()),
+            TokenData::Colon,
+            TokenData::Integer(1),
+            TokenData::Comma,
+            TokenData::Integer(2),
+            TokenData::Colon,
+            TokenData::Nil,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        assert_eq!(
+            program[0],
+            Decl::Stmt(Stmt::Print(e!(ExprData::MapLiteral(vec![
+                (
+                    e!(ExprData::StringLiteral("a".to_string())),
+                    e!(ExprData::IntegerLiteral(1)),
+                ),
+                (e!(ExprData::IntegerLiteral(2)), e!(ExprData::Nil)),
+            ]))))
+        );
+
+        // ({})
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::LeftBrace,
+                TokenData::RightBrace,
+                TokenData::RightParen,
+            ],
+            e!(ExprData::MapLiteral(vec![]))
+        );
+
+        // a bare { starts a block, not a map
+        let program = parse(tokens![
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        // "a ${1} b ${x}"
+        assert_expr_parses!(
+            tokens![
+                TokenData::Interpolation("a ".to_string()),
+                TokenData::Integer(1),
+                TokenData::Interpolation(" b ".to_string()),
+                TokenData::Identifier("x".to_string()),
+                TokenData::StringToken("".to_string()),
+            ],
+            e!(ExprData::Binary(
+                BinOp::Add,
+                e!(ExprData::Binary(
+                    BinOp::Add,
+                    e!(ExprData::Binary(
+                        BinOp::Add,
+                        e!(ExprData::StringLiteral("a ".to_string())).into(),
+                        e!(ExprData::Unary(
+                            UnaryOp::ToString,
+                            e!(ExprData::IntegerLiteral(1)).into()
+                        ))
+                        .into(),
+                    ))
+                    .into(),
+                    e!(ExprData::StringLiteral(" b ".to_string())).into(),
+                ))
+                .into(),
+                e!(ExprData::Unary(
+                    UnaryOp::ToString,
+                    e!(ExprData::Identifier("x".to_string())).into()
+                ))
+                .into(),
+            ))
+        );
+    }
+
+    #[test]
+    fn lambdas() {
+        // fun (a) { return a; }
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Fun,
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::RightParen,
+                TokenData::LeftBrace,
+                TokenData::Return,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Semicolon,
+                TokenData::RightBrace,
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![param("a")],
+                    rest: None,
+                    body: FunctionBody::Block(vec![Decl::Stmt(Stmt::Return(Some(e!(
+                        ExprData::Identifier("a".to_string())
+                    ))))]),
+                    generator: false,
+                }
+                .into()
+            ))
+        );
+
+        // (a, b) => a + b
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Comma,
+                TokenData::Identifier("b".to_string()),
+                TokenData::RightParen,
+                TokenData::Arrow,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Plus,
+                TokenData::Identifier("b".to_string()),
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![param("a"), param("b")],
+                    rest: None,
+                    body: FunctionBody::Expr(e!(ExprData::Binary(
+                        BinOp::Add,
+                        e!(ExprData::Identifier("a".to_string())).into(),
+                        e!(ExprData::Identifier("b".to_string())).into(),
+                    ))),
+                    generator: false,
+                }
+                .into()
+            ))
+        );
+
+        // () => {}
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::RightParen,
+                TokenData::Arrow,
+                TokenData::LeftBrace,
+                TokenData::RightBrace,
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![],
+                    rest: None,
+                    body: FunctionBody::Block(vec![]),
+                    generator: false,
+                }
+                .into()
+            ))
+        );
+
+        // (a) is still a grouping
+        assert_expr_parses!(
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Identifier("a".to_string()))
         );
     }
 
     #[test]
-    fn unary() {
+    fn generators() {
+        // fun* () { yield 1; }
         assert_expr_parses!(
-            tokens![TokenData::Bang, TokenData::False],
-            e!(ExprData::Unary(
-                UnaryOp::Inverse,
-                e!(ExprData::False).into(),
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Fun,
+                TokenData::Star,
+                TokenData::LeftParen,
+                TokenData::RightParen,
+                TokenData::LeftBrace,
+                TokenData::Yield,
+                TokenData::Integer(1),
+                TokenData::Semicolon,
+                TokenData::RightBrace,
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![],
+                    rest: None,
+                    body: FunctionBody::Block(vec![Decl::Stmt(Stmt::Expr(e!(ExprData::Yield(
+                        Some(e!(ExprData::IntegerLiteral(1)).into())
+                    ))))]),
+                    generator: true,
+                }
+                .into()
             ))
         );
 
+        // yield;
+        let program = parse(tokens![
+            TokenData::Yield,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // fun* () { x = yield; }
         assert_expr_parses!(
-            tokens![TokenData::Minus, TokenData::False],
-            e!(ExprData::Unary(
-                UnaryOp::Negative,
-                e!(ExprData::False).into()
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Fun,
+                TokenData::Star,
+                TokenData::LeftParen,
+                TokenData::RightParen,
+                TokenData::LeftBrace,
+                TokenData::Identifier("x".to_string()),
+                TokenData::Equal,
+                TokenData::Yield,
+                TokenData::Semicolon,
+                TokenData::RightBrace,
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![],
+                    rest: None,
+                    body: FunctionBody::Block(vec![Decl::Stmt(Stmt::Expr(e!(ExprData::Assign(
+                        "x".to_string(),
+                        e!(ExprData::Yield(None)).into()
+                    ))))]),
+                    generator: true,
+                }
+                .into()
             ))
         );
+
+        // a yield can't be part of a bigger expression: fun* () { print yield 1; }
+        let program = parse(tokens![
+            TokenData::Fun,
+            TokenData::Star,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Print,
+            TokenData::Yield,
+            TokenData::Integer(1),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // nor a for loop's initializer: fun* () { for (var i = yield; ; ) {} }
+        let program = parse(tokens![
+            TokenData::Fun,
+            TokenData::Star,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::For,
+            TokenData::LeftParen,
+            TokenData::Var,
+            TokenData::Identifier("i".to_string()),
+            TokenData::Equal,
+            TokenData::Yield,
+            TokenData::Semicolon,
+            TokenData::Semicolon,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // a plain function inside a generator can't yield: fun* () { fun () { yield; }; }
+        let program = parse(tokens![
+            TokenData::Fun,
+            TokenData::Star,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Fun,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Yield,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // nor can a try: fun* () { try { yield; } finally {} }
+        let program = parse(tokens![
+            TokenData::Fun,
+            TokenData::Star,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Try,
+            TokenData::LeftBrace,
+            TokenData::Yield,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Finally,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
     }
 
     #[test]
-    fn cmps() {
+    fn function_declarations() {
+        // fun f(a) {}
+        let program = parse(tokens![
+            TokenData::Fun,
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::Identifier("a".to_string()),
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![Decl::VarDecl(
+                e!(ExprData::Identifier("f".to_string())),
+                e!(ExprData::Lambda(
+                    FunctionDecl {
+                        name: Some("f".to_string()),
+                        params: vec![param("a")],
+                        rest: None,
+                        body: FunctionBody::Block(vec![]),
+                        generator: false,
+                    }
+                    .into()
+                )),
+            )]
+        );
+
+        // export fun* g() {}
+        let program = parse(tokens![
+            TokenData::Export,
+            TokenData::Fun,
+            TokenData::Star,
+            TokenData::Identifier("g".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        let Decl::Export(decl) = &program[0] else {
+            panic!("expected an export");
+        };
+        let Decl::VarDecl(_, lambda) = decl.as_ref() else {
+            panic!("expected a var declaration");
+        };
+        assert!(matches!(&lambda.data, ExprData::Lambda(decl) if decl.generator));
+    }
+
+    #[test]
+    fn parameters_and_arguments() {
+        // (a, b = (1), ...rest) => a
         assert_expr_parses!(
-            tokens![TokenData::True, TokenData::Greater, TokenData::False,],
-            e!(ExprData::Binary(
-                BinOp::Gt,
-                e!(ExprData::True).into(),
-                e!(ExprData::False).into()
+            tokens![
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Comma,
+                TokenData::Identifier("b".to_string()),
+                TokenData::Equal,
+                TokenData::LeftParen,
+                TokenData::Integer(1),
+                TokenData::RightParen,
+                TokenData::Comma,
+                TokenData::Ellipsis,
+                TokenData::Identifier("rest".to_string()),
+                TokenData::RightParen,
+                TokenData::Arrow,
+                TokenData::Identifier("a".to_string()),
+            ],
+            e!(ExprData::Lambda(
+                FunctionDecl {
+                    name: None,
+                    params: vec![
+                        param("a"),
+                        Param {
+                            name: "b".to_string(),
+                            default: Some(e!(ExprData::IntegerLiteral(1))),
+                        },
+                    ],
+                    rest: Some("rest".to_string()),
+                    body: FunctionBody::Expr(e!(ExprData::Identifier("a".to_string()))),
+                    generator: false,
+                }
+                .into()
             ))
         );
 
+        // f(1, b: 2)
         assert_expr_parses!(
-            tokens![TokenData::True, TokenData::GreaterEqual, TokenData::False,],
-            e!(ExprData::Binary(
-                BinOp::GtEq,
-                e!(ExprData::True).into(),
-                e!(ExprData::False).into()
+            tokens![
+                TokenData::Identifier("f".to_string()),
+                TokenData::LeftParen,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Identifier("b".to_string()),
+                TokenData::Colon,
+                TokenData::Integer(2),
+                TokenData::RightParen,
+            ],
+            e!(ExprData::Call(
+                e!(ExprData::Identifier("f".to_string())).into(),
+                vec![e!(ExprData::IntegerLiteral(1))],
+                vec![("b".to_string(), e!(ExprData::IntegerLiteral(2)))],
             ))
         );
 
-        assert_expr_parses!(
-            tokens![TokenData::True, TokenData::Less, TokenData::False,],
-            e!(ExprData::Binary(
-                BinOp::Lt,
-                e!(ExprData::True).into(),
-                e!(ExprData::False).into()
+        let invalid = [
+            // a required parameter after a default: fun (a = 1, b) {}
+            tokens![
+                TokenData::Fun,
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Equal,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Identifier("b".to_string()),
+                TokenData::RightParen,
+                TokenData::LeftBrace,
+                TokenData::RightBrace,
+                TokenData::Semicolon,
+                TokenData::Eof,
+            ],
+            // a parameter after the rest parameter: fun (...a, b) {}
+            tokens![
+                TokenData::Fun,
+                TokenData::LeftParen,
+                TokenData::Ellipsis,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Comma,
+                TokenData::Identifier("b".to_string()),
+                TokenData::RightParen,
+                TokenData::LeftBrace,
+                TokenData::RightBrace,
+                TokenData::Semicolon,
+                TokenData::Eof,
+            ],
+            // a positional argument after a named one: f(a: 1, 2)
+            tokens![
+                TokenData::Identifier("f".to_string()),
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Colon,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Integer(2),
+                TokenData::RightParen,
+                TokenData::Semicolon,
+                TokenData::Eof,
+            ],
+            // the same name twice: f(a: 1, a: 2)
+            tokens![
+                TokenData::Identifier("f".to_string()),
+                TokenData::LeftParen,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Colon,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Colon,
+                TokenData::Integer(2),
+                TokenData::RightParen,
+                TokenData::Semicolon,
+                TokenData::Eof,
+            ],
+        ];
+        for tokens in invalid {
+            assert!(parse(tokens).is_err());
+        }
+    }
+
+    #[test]
+    fn return_outside_function() {
+        let program = parse(tokens![
+            TokenData::Return,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn loops() {
+        // for (;;) { continue; }
+        let program = parse(tokens![
+            TokenData::For,
+            TokenData::LeftParen,
+            TokenData::Semicolon,
+            TokenData::Semicolon,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Continue,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        assert_eq!(
+            program[0],
+            Decl::Stmt(Stmt::For(
+                None,
+                None,
+                None,
+                Stmt::Block(vec![Decl::Stmt(Stmt::Continue)]).into()
             ))
         );
 
-        assert_expr_parses!(
-            tokens![TokenData::True, TokenData::LessEqual, TokenData::False,],
-            e!(ExprData::Binary(
-                BinOp::LtEq,
-                e!(ExprData::True).into(),
-                e!(ExprData::False).into()
+        // while (x) x = nil;
+        let program = parse(tokens![
+            TokenData::While,
+            TokenData::LeftParen,
+            TokenData::Identifier("x".to_string()),
+            TokenData::RightParen,
+            TokenData::Identifier("x".to_string()),
+            TokenData::Equal,
+            TokenData::Nil,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        assert_eq!(
+            program[0],
+            Decl::Stmt(Stmt::While(
+                e!(ExprData::Identifier("x".to_string())),
+                Stmt::Expr(e!(ExprData::Assign(
+                    "x".to_string(),
+                    e!(ExprData::Nil).into()
+                )))
+                .into()
             ))
         );
 
-        // left-associativity
-        assert_expr_parses!(
-            tokens![
-                TokenData::Number(1.0),
-                TokenData::LessEqual,
-                TokenData::Number(2.0),
-                TokenData::GreaterEqual,
-                TokenData::Number(3.0),
-            ],
-            e!(ExprData::Binary(
-                BinOp::GtEq,
+        // for (i in 0..n + 1) break;
+        let program = parse(tokens![
+            TokenData::For,
+            TokenData::LeftParen,
+            TokenData::Identifier("i".to_string()),
+            TokenData::In,
+            TokenData::Integer(0),
+            TokenData::DotDot,
+            TokenData::Identifier("n".to_string()),
+            TokenData::Plus,
+            TokenData::Integer(1),
+            TokenData::RightParen,
+            TokenData::Break,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        assert_eq!(
+            program[0],
+            Decl::Stmt(Stmt::ForIn(
+                "i".to_string(),
                 e!(ExprData::Binary(
-                    BinOp::LtEq,
-                    e!(ExprData::NumberLiteral(1.0)).into(),
-                    e!(ExprData::NumberLiteral(2.0)).into(),
-                ))
-                .into(),
-                e!(ExprData::NumberLiteral(3.0)).into()
+                    BinOp::Range,
+                    e!(ExprData::IntegerLiteral(0)).into(),
+                    e!(ExprData::Binary(
+                        BinOp::Add,
+                        e!(ExprData::Identifier("n".to_string())).into(),
+                        e!(ExprData::IntegerLiteral(1)).into(),
+                    ))
+                    .into(),
+                )),
+                Stmt::Break.into()
             ))
         );
+
+        // ranges don't chain: 0..1..2
+        let program = parse(tokens![
+            TokenData::Integer(0),
+            TokenData::DotDot,
+            TokenData::Integer(1),
+            TokenData::DotDot,
+            TokenData::Integer(2),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
     }
 
     #[test]
-    fn math() {
+    fn break_outside_loop() {
+        let program = parse(tokens![
+            TokenData::Break,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // while (true) () => { continue; };
+        let program = parse(tokens![
+            TokenData::While,
+            TokenData::LeftParen,
+            TokenData::True,
+            TokenData::RightParen,
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::Continue,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn ternary() {
+        // right-associative: a ? 1 : b ? 2 : 3
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Plus,
-                TokenData::Number(2.0),
+                TokenData::Identifier("a".to_string()),
+                TokenData::Question,
+                TokenData::Integer(1),
+                TokenData::Colon,
+                TokenData::Identifier("b".to_string()),
+                TokenData::Question,
+                TokenData::Integer(2),
+                TokenData::Colon,
+                TokenData::Integer(3),
             ],
-            e!(ExprData::Binary(
-                BinOp::Add,
-                e!(ExprData::NumberLiteral(1.0)).into(),
-                e!(ExprData::NumberLiteral(2.0)).into(),
+            e!(ExprData::Ternary(
+                e!(ExprData::Identifier("a".to_string())).into(),
+                e!(ExprData::IntegerLiteral(1)).into(),
+                e!(ExprData::Ternary(
+                    e!(ExprData::Identifier("b".to_string())).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                    e!(ExprData::IntegerLiteral(3)).into(),
+                ))
+                .into(),
             ))
         );
 
+        // binds tighter than assignment: x = a ? 1 : 2
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Minus,
-                TokenData::Number(2.0),
+                TokenData::Identifier("x".to_string()),
+                TokenData::Equal,
+                TokenData::Identifier("a".to_string()),
+                TokenData::Question,
+                TokenData::Integer(1),
+                TokenData::Colon,
+                TokenData::Integer(2),
             ],
-            e!(ExprData::Binary(
-                BinOp::Sub,
-                e!(ExprData::NumberLiteral(1.0)).into(),
-                e!(ExprData::NumberLiteral(2.0)).into(),
+            e!(ExprData::Assign(
+                "x".to_string(),
+                e!(ExprData::Ternary(
+                    e!(ExprData::Identifier("a".to_string())).into(),
+                    e!(ExprData::IntegerLiteral(1)).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                ))
+                .into(),
             ))
         );
+    }
 
+    #[test]
+    fn comma() {
+        // left-associative: 1, 2, 3
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Slash,
-                TokenData::Number(2.0),
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::Integer(2),
+                TokenData::Comma,
+                TokenData::Integer(3),
             ],
             e!(ExprData::Binary(
-                BinOp::Div,
-                e!(ExprData::NumberLiteral(1.0)).into(),
-                e!(ExprData::NumberLiteral(2.0)).into(),
+                BinOp::Comma,
+                e!(ExprData::Binary(
+                    BinOp::Comma,
+                    e!(ExprData::IntegerLiteral(1)).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
+                ))
+                .into(),
+                e!(ExprData::IntegerLiteral(3)).into(),
             ))
         );
 
+        // arguments are separated by commas rather than joined: f(1, (2, 3))
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Star,
-                TokenData::Number(2.0),
+                TokenData::Identifier("f".to_string()),
+                TokenData::LeftParen,
+                TokenData::Integer(1),
+                TokenData::Comma,
+                TokenData::LeftParen,
+                TokenData::Integer(2),
+                TokenData::Comma,
+                TokenData::Integer(3),
+                TokenData::RightParen,
+                TokenData::RightParen,
             ],
-            e!(ExprData::Binary(
-                BinOp::Mult,
-                e!(ExprData::NumberLiteral(1.0)).into(),
-                e!(ExprData::NumberLiteral(2.0)).into(),
+            e!(ExprData::Call(
+                e!(ExprData::Identifier("f".to_string())).into(),
+                vec![
+                    e!(ExprData::IntegerLiteral(1)),
+                    e!(ExprData::Binary(
+                        BinOp::Comma,
+                        e!(ExprData::IntegerLiteral(2)).into(),
+                        e!(ExprData::IntegerLiteral(3)).into(),
+                    )),
+                ],
+                vec![],
             ))
         );
+    }
 
-        // left-associative on same operator
+    #[test]
+    fn compound_assignment() {
+        // xs[i] += 1
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Star,
-                TokenData::Number(2.0),
-                TokenData::Star,
-                TokenData::Number(3.0),
+                TokenData::Identifier("xs".to_string()),
+                TokenData::LeftBracket,
+                TokenData::Identifier("i".to_string()),
+                TokenData::RightBracket,
+                TokenData::PlusEqual,
+                TokenData::Integer(1),
             ],
-            e!(ExprData::Binary(
-                BinOp::Mult,
-                e!(ExprData::Binary(
-                    BinOp::Mult,
-                    e!(ExprData::NumberLiteral(1.0)).into(),
-                    e!(ExprData::NumberLiteral(2.0)).into(),
+            e!(ExprData::CompoundAssign(
+                BinOp::Add,
+                e!(ExprData::Index(
+                    e!(ExprData::Identifier("xs".to_string())).into(),
+                    e!(ExprData::Identifier("i".to_string())).into(),
                 ))
                 .into(),
-                e!(ExprData::NumberLiteral(3.0)).into(),
+                e!(ExprData::IntegerLiteral(1)).into(),
             ))
         );
 
-        // mult takes precedence over add
+        // right-associative: a *= b /= 2
         assert_expr_parses!(
             tokens![
-                TokenData::Number(1.0),
-                TokenData::Plus,
-                TokenData::Number(2.0),
-                TokenData::Star,
-                TokenData::Number(3.0),
+                TokenData::Identifier("a".to_string()),
+                TokenData::StarEqual,
+                TokenData::Identifier("b".to_string()),
+                TokenData::SlashEqual,
+                TokenData::Integer(2),
             ],
-            e!(ExprData::Binary(
-                BinOp::Add,
-                e!(ExprData::NumberLiteral(1.0)).into(),
-                e!(ExprData::Binary(
-                    BinOp::Mult,
-                    e!(ExprData::NumberLiteral(2.0)).into(),
-                    e!(ExprData::NumberLiteral(3.0)).into(),
+            e!(ExprData::CompoundAssign(
+                BinOp::Mult,
+                e!(ExprData::Identifier("a".to_string())).into(),
+                e!(ExprData::CompoundAssign(
+                    BinOp::Div,
+                    e!(ExprData::Identifier("b".to_string())).into(),
+                    e!(ExprData::IntegerLiteral(2)).into(),
                 ))
                 .into(),
             ))
         );
+
+        let program = parse(tokens![
+            TokenData::Integer(1),
+            TokenData::MinusEqual,
+            TokenData::Integer(2),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
     }
 
     #[test]
-    fn grouping() {
+    fn increment() {
+        // prefix is sugar for += 1: --x
         assert_expr_parses!(
             tokens![
-                TokenData::LeftParen,
-                TokenData::Number(1.0),
-                TokenData::Plus,
-                TokenData::Number(2.0),
-                TokenData::RightParen,
-                TokenData::Star,
-                TokenData::Number(3.0),
+                TokenData::MinusMinus,
+                TokenData::Identifier("x".to_string())
             ],
-            e!(ExprData::Binary(
-                BinOp::Mult,
-                e!(ExprData::Binary(
+            e!(ExprData::CompoundAssign(
+                BinOp::Sub,
+                e!(ExprData::Identifier("x".to_string())).into(),
+                e!(ExprData::IntegerLiteral(1)).into(),
+            ))
+        );
+
+        // postfix binds tighter than prefix operators: -x++
+        assert_expr_parses!(
+            tokens![
+                TokenData::Minus,
+                TokenData::Identifier("x".to_string()),
+                TokenData::PlusPlus,
+            ],
+            e!(ExprData::Unary(
+                UnaryOp::Negative,
+                e!(ExprData::PostfixUpdate(
                     BinOp::Add,
-                    e!(ExprData::NumberLiteral(1.0)).into(),
-                    e!(ExprData::NumberLiteral(2.0)).into(),
+                    e!(ExprData::Identifier("x".to_string())).into(),
                 ))
                 .into(),
-                e!(ExprData::NumberLiteral(3.0)).into(),
             ))
         );
+
+        let program = parse(tokens![
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::PlusPlus,
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn try_catch() {
+        // try { throw 1; } catch (e) { print e; } finally { }
+        let program = parse(tokens![
+            TokenData::Try,
+            TokenData::LeftBrace,
+            TokenData::Throw,
+            TokenData::Integer(1),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Catch,
+            TokenData::LeftParen,
+            TokenData::Identifier("e".to_string()),
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Print,
+            TokenData::Identifier("e".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Finally,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![Decl::Stmt(Stmt::Try(
+                vec![Decl::Stmt(Stmt::Throw(e!(ExprData::IntegerLiteral(1))))],
+                Some((
+                    "e".to_string(),
+                    vec![Decl::Stmt(Stmt::Print(e!(ExprData::Identifier(
+                        "e".to_string()
+                    ))))],
+                )),
+                Some(vec![]),
+            ))]
+        );
+
+        // a try needs a catch or a finally
+        let program = parse(tokens![
+            TokenData::Try,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn match_statement() {
+        // match x { -1 | [_, y] if y => print y; }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Minus,
+            TokenData::Integer(1),
+            TokenData::Pipe,
+            TokenData::LeftBracket,
+            TokenData::Identifier("_".to_string()),
+            TokenData::Comma,
+            TokenData::Identifier("y".to_string()),
+            TokenData::RightBracket,
+            TokenData::If,
+            TokenData::Identifier("y".to_string()),
+            TokenData::Arrow,
+            TokenData::Print,
+            TokenData::Identifier("y".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![Decl::Stmt(Stmt::Match(
+                e!(ExprData::Identifier("x".to_string())),
+                vec![MatchArm {
+                    pattern: Pattern::Or(vec![
+                        Pattern::Literal(Value::Int(-1)),
+                        Pattern::List(vec![Pattern::Wildcard, Pattern::Binding("y".to_string())]),
+                    ]),
+                    guard: Some(e!(ExprData::Identifier("y".to_string()))),
+                    body: Stmt::Print(e!(ExprData::Identifier("y".to_string()))),
+                    line: 0,
+                }],
+            ))]
+        );
+
+        // match x { n if n > (y) => print n; }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("n".to_string()),
+            TokenData::If,
+            TokenData::Identifier("n".to_string()),
+            TokenData::Greater,
+            TokenData::LeftParen,
+            TokenData::Identifier("y".to_string()),
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::Print,
+            TokenData::Identifier("n".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![Decl::Stmt(Stmt::Match(
+                e!(ExprData::Identifier("x".to_string())),
+                vec![MatchArm {
+                    pattern: Pattern::Binding("n".to_string()),
+                    guard: Some(e!(ExprData::Binary(
+                        BinOp::Gt,
+                        e!(ExprData::Identifier("n".to_string())).into(),
+                        e!(ExprData::Identifier("y".to_string())).into(),
+                    ))),
+                    body: Stmt::Print(e!(ExprData::Identifier("n".to_string()))),
+                    line: 0,
+                }],
+            ))]
+        );
+
+        // match x { n if (n) => {} }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("n".to_string()),
+            TokenData::If,
+            TokenData::LeftParen,
+            TokenData::Identifier("n".to_string()),
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        let Decl::Stmt(Stmt::Match(_, arms)) = &program[0] else {
+            panic!("expected a match statement");
+        };
+        assert_eq!(
+            arms[0].guard,
+            Some(e!(ExprData::Identifier("n".to_string())))
+        );
+
+        // arrow functions still work inside brackets in a guard
+        // match x { n if f((a) => a) => {} }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("n".to_string()),
+            TokenData::If,
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::LeftParen,
+            TokenData::Identifier("a".to_string()),
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::Identifier("a".to_string()),
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        let Decl::Stmt(Stmt::Match(_, arms)) = &program[0] else {
+            panic!("expected a match statement");
+        };
+        assert!(matches!(
+            &arms[0].guard.as_ref().unwrap().data,
+            ExprData::Call(_, args, _) if matches!(args[0].data, ExprData::Lambda(_))
+        ));
+
+        // match x { P { a, b: 1 } => {} }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("P".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("a".to_string()),
+            TokenData::Comma,
+            TokenData::Identifier("b".to_string()),
+            TokenData::Colon,
+            TokenData::Integer(1),
+            TokenData::RightBrace,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+        let Decl::Stmt(Stmt::Match(_, arms)) = &program[0] else {
+            panic!("expected a match statement");
+        };
+        assert_eq!(
+            arms[0].pattern,
+            Pattern::Instance(
+                "P".to_string(),
+                vec![
+                    ("a".to_string(), Pattern::Binding("a".to_string())),
+                    ("b".to_string(), Pattern::Literal(Value::Int(1))),
+                ]
+            )
+        );
+
+        // match x { P { a, a } => {} }
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("P".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("a".to_string()),
+            TokenData::Comma,
+            TokenData::Identifier("a".to_string()),
+            TokenData::RightBrace,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // patterns can't be arbitrary expressions
+        let program = parse(tokens![
+            TokenData::Match,
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftBrace,
+            TokenData::LeftParen,
+            TokenData::Integer(1),
+            TokenData::RightParen,
+            TokenData::Arrow,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn modules() {
+        // import "lib.lox" as lib; export var x = lib;
+        let program = parse(tokens![
+            TokenData::Import,
+            TokenData::StringToken("lib.lox".to_string()),
+            TokenData::As,
+            TokenData::Identifier("lib".to_string()),
+            TokenData::Semicolon,
+            TokenData::Export,
+            TokenData::Var,
+            TokenData::Identifier("x".to_string()),
+            TokenData::Equal,
+            TokenData::Identifier("lib".to_string()),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![
+                Decl::Import("lib.lox".to_string(), "lib".to_string(), 0),
+                Decl::Export(
+                    Decl::VarDecl(
+                        e!(ExprData::Identifier("x".to_string())),
+                        e!(ExprData::Identifier("lib".to_string())),
+                    )
+                    .into()
+                ),
+            ]
+        );
+
+        // only at the top level: { import "lib.lox" as lib; }
+        let program = parse(tokens![
+            TokenData::LeftBrace,
+            TokenData::Import,
+            TokenData::StringToken("lib.lox".to_string()),
+            TokenData::As,
+            TokenData::Identifier("lib".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // export print 1;
+        let program = parse(tokens![
+            TokenData::Export,
+            TokenData::Print,
+            TokenData::Integer(1),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn const_declaration() {
+        // export const x = 1;
+        let program = parse(tokens![
+            TokenData::Export,
+            TokenData::Const,
+            TokenData::Identifier("x".to_string()),
+            TokenData::Equal,
+            TokenData::Integer(1),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![Decl::Export(
+                Decl::ConstDecl(
+                    e!(ExprData::Identifier("x".to_string())),
+                    e!(ExprData::IntegerLiteral(1)),
+                )
+                .into()
+            )]
+        );
+
+        // a const needs an initializer: const x;
+        let program = parse(tokens![
+            TokenData::Const,
+            TokenData::Identifier("x".to_string()),
+            TokenData::Semicolon,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn classes() {
+        // class B < A { size { return this.n; } class make() { return super.make(); } }
+        let program = parse(tokens![
+            TokenData::Class,
+            TokenData::Identifier("B".to_string()),
+            TokenData::Less,
+            TokenData::Identifier("A".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("size".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Return,
+            TokenData::This,
+            TokenData::Dot,
+            TokenData::Identifier("n".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::Class,
+            TokenData::Identifier("make".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Return,
+            TokenData::Super,
+            TokenData::Dot,
+            TokenData::Identifier("make".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        let method = |name: &str, kind, ret| Method {
+            name: name.to_string(),
+            kind,
+            decl: FunctionDecl {
+                name: Some(name.to_string()),
+                params: vec![],
+                rest: None,
+                body: FunctionBody::Block(vec![Decl::Stmt(Stmt::Return(Some(ret)))]),
+                generator: false,
+            }
+            .into(),
+        };
+        assert_eq!(
+            program,
+            vec![Decl::Class(
+                ClassDecl {
+                    name: "B".to_string(),
+                    superclass: Some(e!(ExprData::Identifier("A".to_string()))),
+                    traits: vec![],
+                    methods: vec![
+                        method(
+                            "size",
+                            MethodKind::Getter,
+                            e!(ExprData::Get(e!(ExprData::This).into(), "n".to_string())),
+                        ),
+                        method(
+                            "make",
+                            MethodKind::Static,
+                            e!(ExprData::Call(
+                                e!(ExprData::Super("make".to_string())).into(),
+                                vec![],
+                                vec![]
+                            )),
+                        ),
+                    ],
+                    line: 0,
+                }
+                .into()
+            )]
+        );
+
+        // this outside a class: this;
+        let program = parse(tokens![
+            TokenData::This,
+            TokenData::Semicolon,
+            TokenData::Eof
+        ]);
+        assert!(program.is_err());
+
+        // super without a superclass: class A { f() { super.f; } }
+        let program = parse(tokens![
+            TokenData::Class,
+            TokenData::Identifier("A".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Super,
+            TokenData::Dot,
+            TokenData::Identifier("f".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+
+        // a setter takes one parameter: class A { set x() {} }
+        let program = parse(tokens![
+            TokenData::Class,
+            TokenData::Identifier("A".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("set".to_string()),
+            TokenData::Identifier("x".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
+    }
+
+    #[test]
+    fn traits() {
+        // trait T { f() { return this; } } class C with T, U {}
+        let program = parse(tokens![
+            TokenData::Trait,
+            TokenData::Identifier("T".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Return,
+            TokenData::This,
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Class,
+            TokenData::Identifier("C".to_string()),
+            TokenData::With,
+            TokenData::Identifier("T".to_string()),
+            TokenData::Comma,
+            TokenData::Identifier("U".to_string()),
+            TokenData::LeftBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![
+                Decl::Trait(
+                    TraitDecl {
+                        name: "T".to_string(),
+                        methods: vec![Method {
+                            name: "f".to_string(),
+                            kind: MethodKind::Method,
+                            decl: FunctionDecl {
+                                name: Some("f".to_string()),
+                                params: vec![],
+                                rest: None,
+                                body: FunctionBody::Block(vec![Decl::Stmt(Stmt::Return(Some(
+                                    e!(ExprData::This)
+                                )))]),
+                                generator: false,
+                            }
+                            .into(),
+                        }],
+                        line: 0,
+                    }
+                    .into()
+                ),
+                Decl::Class(
+                    ClassDecl {
+                        name: "C".to_string(),
+                        superclass: None,
+                        traits: vec![
+                            e!(ExprData::Identifier("T".to_string())),
+                            e!(ExprData::Identifier("U".to_string())),
+                        ],
+                        methods: vec![],
+                        line: 0,
+                    }
+                    .into()
+                ),
+            ]
+        );
+
+        // no super in traits: trait T { f() { super.f; } }
+        let program = parse(tokens![
+            TokenData::Trait,
+            TokenData::Identifier("T".to_string()),
+            TokenData::LeftBrace,
+            TokenData::Identifier("f".to_string()),
+            TokenData::LeftParen,
+            TokenData::RightParen,
+            TokenData::LeftBrace,
+            TokenData::Super,
+            TokenData::Dot,
+            TokenData::Identifier("f".to_string()),
+            TokenData::Semicolon,
+            TokenData::RightBrace,
+            TokenData::RightBrace,
+            TokenData::Eof,
+        ]);
+        assert!(program.is_err());
     }
 }
@@ -1,4 +1,9 @@
-use crate::expr::{BinOp, Decl, Expr, ExprData, Stmt, UnaryOp};
+use std::fmt::Display;
+
+use crate::eval::{format_number, Value};
+use crate::expr::{
+    BinOp, Decl, Expr, ExprData, FunctionBody, Method, MethodKind, Pattern, Stmt, UnaryOp,
+};
 
 macro_rules! indent {
     ( $v:expr, $n:expr) => {{
@@ -21,17 +26,189 @@ impl Decl {
                 e.pretty();
             }
 
+            Decl::ConstDecl(id, e) => {
+                println!("const {id:?} =");
+                e.pretty();
+            }
+
+            Decl::Class(class) => {
+                println!("class {}", class.name);
+                if let Some(superclass) = &class.superclass {
+                    println!("<");
+                    superclass.pretty();
+                }
+                if !class.traits.is_empty() {
+                    println!("with");
+                    for t in &class.traits {
+                        t.pretty();
+                    }
+                }
+                pretty_methods(&class.methods);
+            }
+
+            Decl::Trait(t) => {
+                println!("trait {}", t.name);
+                pretty_methods(&t.methods);
+            }
+
             Decl::Stmt(e) => e.pretty(),
+            Decl::Import(path, name, _) => println!("import \"{path}\" as {name}"),
+            Decl::Export(decl) => {
+                println!("export");
+                decl.pretty();
+            }
         }
         println!();
     }
 }
 
+// Prints the methods of a class or trait body.
+fn pretty_methods(methods: &[Method]) {
+    for method in methods {
+        let kind = match method.kind {
+            MethodKind::Method => "",
+            MethodKind::Static => "class ",
+            MethodKind::Getter => "get ",
+            MethodKind::Setter => "set ",
+        };
+        println!("{kind}{}", method.name);
+        ExprData::Lambda(method.decl.clone()).pretty_recur(4);
+    }
+}
+
 impl Stmt {
     pub fn pretty(&self) {
         match self {
             Stmt::Expr(e) => e.pretty(),
             Stmt::Print(e) => e.pretty(),
+            Stmt::Return(Some(e)) => {
+                println!("return");
+                e.pretty();
+            }
+            Stmt::Return(None) => println!("return"),
+            Stmt::Block(decls) => {
+                println!("{{");
+                for decl in decls {
+                    decl.pretty();
+                }
+                println!("}}");
+            }
+            Stmt::If(cond, then_branch, else_branch) => {
+                println!("if");
+                cond.pretty();
+                println!("then");
+                then_branch.pretty();
+                if let Some(else_branch) = else_branch {
+                    println!("else");
+                    else_branch.pretty();
+                }
+            }
+            Stmt::While(cond, body) => {
+                println!("while");
+                cond.pretty();
+                println!("do");
+                body.pretty();
+            }
+            Stmt::For(init, cond, incr, body) => {
+                println!("for");
+                if let Some(init) = init {
+                    init.pretty();
+                }
+                println!(";");
+                if let Some(cond) = cond {
+                    cond.pretty();
+                }
+                println!(";");
+                if let Some(incr) = incr {
+                    incr.pretty();
+                }
+                println!("do");
+                body.pretty();
+            }
+            Stmt::ForIn(name, iterable, body) => {
+                println!("for {name} in");
+                iterable.pretty();
+                println!("do");
+                body.pretty();
+            }
+            Stmt::Break => println!("break"),
+            Stmt::Continue => println!("continue"),
+            Stmt::Throw(e) => {
+                println!("throw");
+                e.pretty();
+            }
+            Stmt::Try(body, catch, finally) => {
+                println!("try {{");
+                for decl in body {
+                    decl.pretty();
+                }
+                if let Some((name, handler)) = catch {
+                    println!("}} catch ({name}) {{");
+                    for decl in handler {
+                        decl.pretty();
+                    }
+                }
+                if let Some(finally) = finally {
+                    println!("}} finally {{");
+                    for decl in finally {
+                        decl.pretty();
+                    }
+                }
+                println!("}}");
+            }
+            Stmt::Match(subject, arms) => {
+                println!("match");
+                subject.pretty();
+                for arm in arms {
+                    println!("{}", arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        println!("if");
+                        guard.pretty();
+                    }
+                    println!("=>");
+                    arm.body.pretty();
+                }
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{name}"),
+            Pattern::Literal(Value::String(s)) => write!(f, "\"{s}\""),
+            Pattern::Literal(v) => write!(f, "{v}"),
+            Pattern::List(elements) => {
+                write!(f, "[")?;
+                for (i, p) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{p}")?;
+                }
+                write!(f, "]")
+            }
+            Pattern::Or(alternatives) => {
+                for (i, p) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{p}")?;
+                }
+                Ok(())
+            }
+            Pattern::Instance(class, fields) => {
+                write!(f, "{class} {{")?;
+                for (i, (name, p)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {name}: {p}")?;
+                }
+                write!(f, " }}")
+            }
         }
     }
 }
@@ -49,26 +226,127 @@ impl Expr {
 impl ExprData {
     pub fn pretty_recur(&self, indent: usize) {
         match self {
-            Self::Binary(op, left, right) => {
-                let op = match op {
-                    BinOp::Eq => "==",
-                    BinOp::Neq => "!=",
-                    BinOp::Gt => ">",
-                    BinOp::GtEq => ">=",
-                    BinOp::Lt => "<",
-                    BinOp::LtEq => "<=",
-                    BinOp::Add => "+",
-                    BinOp::Sub => "-",
-                    BinOp::Div => "/",
-                    BinOp::Mult => "*",
-                };
-                pretty!(op, left, right, indent)
+            Self::Binary(op, left, right) => pretty!(op.symbol(), left, right, indent),
+
+            Self::Call(callee, args, named) => {
+                indent!("call", indent);
+                callee.pretty_recur(indent + 4);
+                indent!("with", indent);
+                for arg in args {
+                    arg.pretty_recur(indent + 4);
+                }
+                for (name, arg) in named {
+                    indent!(format!("{name}:"), indent + 4);
+                    arg.pretty_recur(indent + 8);
+                }
+            }
+
+            Self::Index(target, index) => {
+                target.pretty_recur(indent + 4);
+                indent!("[]", indent);
+                index.pretty_recur(indent + 4);
+            }
+
+            Self::IndexSet(target, index, value) => {
+                target.pretty_recur(indent + 4);
+                indent!("[]", indent);
+                index.pretty_recur(indent + 4);
+                indent!("=", indent);
+                value.pretty_recur(indent + 4);
+            }
+
+            Self::Assign(id, value) => {
+                indent!(format!("{id} ="), indent);
+                value.pretty_recur(indent + 4);
+            }
+
+            Self::CompoundAssign(op, target, value) => {
+                pretty!(format!("{}=", op.symbol()), target, value, indent)
+            }
+
+            Self::PostfixUpdate(op, target) => {
+                target.pretty_recur(indent + 4);
+                indent!(format!("{0}{0} (postfix)", op.symbol()), indent);
+            }
+
+            Self::Ternary(cond, then_expr, else_expr) => {
+                cond.pretty_recur(indent + 4);
+                indent!("?", indent);
+                then_expr.pretty_recur(indent + 4);
+                indent!(":", indent);
+                else_expr.pretty_recur(indent + 4);
+            }
+
+            Self::ListLiteral(elements) => {
+                indent!("[", indent);
+                for e in elements {
+                    e.pretty_recur(indent + 4);
+                }
+                indent!("]", indent);
             }
 
+            Self::MapLiteral(entries) => {
+                indent!("{", indent);
+                for (k, v) in entries {
+                    k.pretty_recur(indent + 4);
+                    indent!(":", indent + 4);
+                    v.pretty_recur(indent + 8);
+                }
+                indent!("}", indent);
+            }
+
+            Self::Lambda(decl) => {
+                let params = decl
+                    .params
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .chain(decl.rest.iter().map(|rest| format!("...{rest}")))
+                    .collect::<Vec<_>>();
+                let fun = if decl.generator { "fun*" } else { "fun" };
+                let name = decl.name.as_deref().unwrap_or("");
+                indent!(format!("{fun} {name}({})", params.join(", ")), indent);
+                for p in &decl.params {
+                    if let Some(default) = &p.default {
+                        indent!(format!("{} defaults to", p.name), indent + 4);
+                        default.pretty_recur(indent + 8);
+                    }
+                }
+                match &decl.body {
+                    FunctionBody::Expr(e) => e.pretty_recur(indent + 4),
+                    FunctionBody::Block(decls) => {
+                        for decl in decls {
+                            decl.pretty();
+                        }
+                    }
+                }
+            }
+
+            Self::Get(object, name) => {
+                object.pretty_recur(indent + 4);
+                indent!(format!(".{name}"), indent);
+            }
+
+            Self::Set(object, name, value) => {
+                object.pretty_recur(indent + 4);
+                indent!(format!(".{name} ="), indent);
+                value.pretty_recur(indent + 4);
+            }
+
+            Self::Super(name) => indent!(format!("super.{name}"), indent),
+            Self::Yield(value) => {
+                indent!("yield", indent);
+                if let Some(value) = value {
+                    value.pretty_recur(indent + 4);
+                }
+            }
+            Self::This => indent!("this", indent),
+
             Self::Unary(op, e) => {
                 let op = match op {
                     UnaryOp::Negative => "-",
                     UnaryOp::Inverse => "!",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::ToString => "to_string",
                 };
                 println!("{}{}", " ".repeat(indent), op);
                 e.pretty_recur(indent + 4);
@@ -76,10 +354,39 @@ impl ExprData {
 
             Self::Identifier(s) => indent!(format!("{s}"), indent),
             Self::StringLiteral(s) => indent!(format!("\"{s}\""), indent),
-            Self::NumberLiteral(n) => indent!(format!("{n}"), indent),
+            Self::NumberLiteral(n) => indent!(format_number(*n), indent),
+            Self::IntegerLiteral(n) => indent!(format!("{n}"), indent),
+            Self::BigIntegerLiteral(n) => indent!(format!("{n}"), indent),
             Self::True => indent!("true", indent),
             Self::False => indent!("false", indent),
             Self::Nil => indent!("nil", indent),
         }
     }
 }
+
+impl BinOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Eq => "==",
+            BinOp::Neq => "!=",
+            BinOp::Gt => ">",
+            BinOp::GtEq => ">=",
+            BinOp::Lt => "<",
+            BinOp::LtEq => "<=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Div => "/",
+            BinOp::Mult => "*",
+            BinOp::Mod => "%",
+            BinOp::FloorDiv => "~/",
+            BinOp::Pow => "**",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Range => "..",
+            BinOp::Comma => ",",
+        }
+    }
+}
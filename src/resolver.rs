@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, ErrorState};
+use crate::expr::{Decl, Expr, ExprData, FunctionBody, FunctionDecl, Pattern, Program, Stmt};
+
+// Each name declared in a scope, and whether it's a const.
+type Scope = HashMap<String, bool>;
+
+/// A static pass over a program before it runs, which rejects assignments to consts, redeclarations
+/// of them in the same scope and or-patterns whose alternatives bind different names, and warns
+/// about match arms that can't be reached.
+///
+/// Global consts are remembered from one program to the next once their declarations have run
+/// (see `declared`), so that the REPL can't redefine them on a later line. Other globals aren't, so
+/// a later line can redeclare a var as a const, just as it can redeclare it as a var.
+#[derive(Default)]
+pub struct Resolver {
+    // only ever holds consts
+    globals: Scope,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the program, returning its warnings if it has no errors.
+    pub fn resolve(&mut self, program: &Program) -> Result<Vec<Error>, ErrorState> {
+        let mut pass = Pass {
+            scopes: vec![self.globals.clone()],
+            err_state: ErrorState::new_resolver_state(),
+            warnings: vec![],
+        };
+
+        for decl in program {
+            pass.decl(decl);
+        }
+
+        if pass.err_state.is_ok() {
+            Ok(pass.warnings)
+        } else {
+            Err(pass.err_state)
+        }
+    }
+
+    /// Records a top-level declaration that has run, so that later programs are checked against
+    /// it. A declaration that fails, like `const x = nope;`, doesn't reserve its name.
+    pub fn declared(&mut self, decl: &Decl) {
+        match decl {
+            Decl::ConstDecl(id, _) => {
+                if let ExprData::Identifier(name) = &id.data {
+                    self.globals.insert(name.clone(), true);
+                }
+            }
+            Decl::VarDecl(..) | Decl::Class(_) | Decl::Trait(_) | Decl::Import(..) => (),
+            Decl::Export(decl) => self.declared(decl),
+            Decl::Stmt(_) => (),
+        }
+    }
+}
+
+struct Pass {
+    // innermost last
+    scopes: Vec<Scope>,
+    err_state: ErrorState,
+    warnings: Vec<Error>,
+}
+
+impl Pass {
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, is_const: bool, line: u32) {
+        let scope = self.scopes.last_mut().unwrap();
+
+        // Vars can be redeclared, but not if either declaration is a const.
+        match scope.get(name) {
+            Some(true) => self.err_state.add(Error::resolve_error(
+                format!("can't redeclare const {name}"),
+                line,
+            )),
+            Some(false) if is_const => self.err_state.add(Error::resolve_error(
+                format!("can't declare const {name}, since it's already declared in this scope"),
+                line,
+            )),
+            _ => {
+                scope.insert(name.to_string(), is_const);
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &str, line: u32) {
+        // Names that aren't declared anywhere are left for the runtime to report.
+        let is_const = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+
+        if is_const == Some(&true) {
+            self.err_state.add(Error::resolve_error(
+                format!("can't assign to const {name}"),
+                line,
+            ));
+        }
+    }
+
+    fn decls(&mut self, decls: &[Decl]) {
+        for decl in decls {
+            self.decl(decl);
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::VarDecl(id, init) | Decl::ConstDecl(id, init) => {
+                self.expr(init);
+
+                if let ExprData::Identifier(name) = &id.data {
+                    self.declare(name, matches!(decl, Decl::ConstDecl(..)), id.line);
+                }
+            }
+            Decl::Class(class) => {
+                if let Some(superclass) = &class.superclass {
+                    if superclass.data == ExprData::Identifier(class.name.clone()) {
+                        self.err_state.add(Error::resolve_error(
+                            format!("class {} can't inherit from itself", class.name),
+                            superclass.line,
+                        ));
+                    }
+                    self.expr(superclass);
+                }
+                for t in &class.traits {
+                    self.expr(t);
+                }
+
+                self.declare(&class.name, false, class.line);
+                for method in &class.methods {
+                    self.function(&method.decl, class.line);
+                }
+            }
+            Decl::Trait(t) => {
+                self.declare(&t.name, false, t.line);
+                for method in &t.methods {
+                    self.function(&method.decl, t.line);
+                }
+            }
+            Decl::Stmt(stmt) => self.stmt(stmt),
+            Decl::Import(_, name, line) => self.declare(name, false, *line),
+            Decl::Export(decl) => self.decl(decl),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Print(e) | Stmt::Throw(e) | Stmt::Return(Some(e)) => self.expr(e),
+            Stmt::Return(None) | Stmt::Break | Stmt::Continue => (),
+            Stmt::Block(decls) => self.scoped(|pass| pass.decls(decls)),
+            Stmt::If(cond, then_branch, else_branch) => {
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::While(cond, body) => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            Stmt::For(init, cond, incr, body) => self.scoped(|pass| {
+                if let Some(init) = init {
+                    pass.decl(init);
+                }
+                if let Some(cond) = cond {
+                    pass.expr(cond);
+                }
+                if let Some(incr) = incr {
+                    pass.expr(incr);
+                }
+                pass.stmt(body);
+            }),
+            Stmt::ForIn(name, iterable, body) => {
+                self.expr(iterable);
+                self.scoped(|pass| {
+                    pass.scopes.last_mut().unwrap().insert(name.clone(), false);
+                    pass.stmt(body);
+                });
+            }
+            Stmt::Try(body, catch, finally) => {
+                self.scoped(|pass| pass.decls(body));
+                if let Some((name, handler)) = catch {
+                    self.scoped(|pass| {
+                        pass.scopes.last_mut().unwrap().insert(name.clone(), false);
+                        pass.decls(handler);
+                    });
+                }
+                if let Some(finally) = finally {
+                    self.scoped(|pass| pass.decls(finally));
+                }
+            }
+            Stmt::Match(subject, arms) => {
+                self.expr(subject);
+
+                // whether an earlier arm matches everything, making the rest unreachable
+                let mut exhausted = false;
+                for arm in arms {
+                    if exhausted {
+                        self.warnings
+                            .push(Error::warning("unreachable match arm".into(), arm.line));
+                    }
+                    exhausted |= arm.guard.is_none() && arm.pattern.is_irrefutable();
+
+                    self.scoped(|pass| {
+                        pass.pattern(&arm.pattern, arm.line);
+                        if let Some(guard) = &arm.guard {
+                            pass.expr(guard);
+                        }
+                        pass.stmt(&arm.body);
+                    });
+                }
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, line: u32) {
+        match pattern {
+            Pattern::Binding(name) => {
+                self.scopes.last_mut().unwrap().insert(name.clone(), false);
+            }
+            Pattern::List(patterns) => {
+                for p in patterns {
+                    self.pattern(p, line);
+                }
+            }
+            // whichever alternative matches, the guard and body need the same names bound
+            Pattern::Or(alternatives) => {
+                let names = alternatives[0].bindings();
+                if alternatives[1..].iter().any(|p| p.bindings() != names) {
+                    self.err_state.add(Error::resolve_error(
+                        "every alternative of a pattern must bind the same names".into(),
+                        line,
+                    ));
+                }
+                for p in alternatives {
+                    self.pattern(p, line);
+                }
+            }
+            Pattern::Instance(_, fields) => {
+                for (_, p) in fields {
+                    self.pattern(p, line);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.data {
+            ExprData::Assign(name, value) => {
+                self.expr(value);
+                self.assign(name, expr.line);
+            }
+            ExprData::CompoundAssign(_, target, value) => {
+                self.update_target(target);
+                self.expr(value);
+            }
+            ExprData::PostfixUpdate(_, target) => self.update_target(target),
+
+            ExprData::Lambda(decl) => self.function(decl, expr.line),
+            ExprData::Yield(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprData::Get(object, _) => self.expr(object),
+            ExprData::Set(object, _, value) => {
+                self.expr(object);
+                self.expr(value);
+            }
+
+            ExprData::Binary(_, left, right) | ExprData::Index(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprData::IndexSet(target, index, value) => {
+                self.expr(target);
+                self.expr(index);
+                self.expr(value);
+            }
+            ExprData::Ternary(cond, then_expr, else_expr) => {
+                self.expr(cond);
+                self.expr(then_expr);
+                self.expr(else_expr);
+            }
+            ExprData::Unary(_, e) => self.expr(e),
+            ExprData::Call(callee, args, named) => {
+                self.expr(callee);
+                for arg in args.iter().chain(named.iter().map(|(_, arg)| arg)) {
+                    self.expr(arg);
+                }
+            }
+            ExprData::ListLiteral(elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            ExprData::MapLiteral(entries) => {
+                for (k, v) in entries {
+                    self.expr(k);
+                    self.expr(v);
+                }
+            }
+
+            ExprData::NumberLiteral(_)
+            | ExprData::IntegerLiteral(_)
+            | ExprData::BigIntegerLiteral(_)
+            | ExprData::Identifier(_)
+            | ExprData::StringLiteral(_)
+            | ExprData::Super(_)
+            | ExprData::This
+            | ExprData::True
+            | ExprData::False
+            | ExprData::Nil => (),
+        }
+    }
+
+    fn function(&mut self, decl: &FunctionDecl, line: u32) {
+        self.scoped(|pass| {
+            // defaults can refer to the parameters before them
+            for param in &decl.params {
+                if let Some(default) = &param.default {
+                    pass.expr(default);
+                }
+                pass.declare(&param.name, false, line);
+            }
+            if let Some(rest) = &decl.rest {
+                pass.declare(rest, false, line);
+            }
+            match &decl.body {
+                FunctionBody::Block(decls) => pass.decls(decls),
+                FunctionBody::Expr(e) => pass.expr(e),
+            }
+        });
+    }
+
+    fn update_target(&mut self, target: &Expr) {
+        match &target.data {
+            ExprData::Identifier(name) => self.assign(name, target.line),
+            _ => self.expr(target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::test_util::parse;
+
+    fn resolve(resolver: &mut Resolver, source: &str) -> bool {
+        resolver.resolve(&parse(source)).is_ok()
+    }
+
+    // Resolves a program and records its declarations as if it ran successfully.
+    fn run(resolver: &mut Resolver, source: &str) -> bool {
+        let program = parse(source);
+        let ok = resolver.resolve(&program).is_ok();
+        if ok {
+            for decl in &program {
+                resolver.declared(decl);
+            }
+        }
+        ok
+    }
+
+    #[test]
+    fn assignment() {
+        let mut r = Resolver::new();
+        assert!(!resolve(&mut r, "const x = 1; x = 2;"));
+        assert!(!resolve(&mut r, "const x = 1; x += 2;"));
+        assert!(!resolve(&mut r, "const x = [1]; var f = () => x++;"));
+
+        // elements of a const list can still change
+        assert!(resolve(&mut r, "const xs = [1]; xs[0] = 2;"));
+        // shadowing in an inner scope is fine
+        assert!(resolve(&mut r, "const y = 1; { var y = 2; y = 3; }"));
+        assert!(resolve(&mut r, "const z = 1; var f = (z) => { z = 2; };"));
+    }
+
+    #[test]
+    fn redeclaration() {
+        assert!(!resolve(&mut Resolver::new(), "const x = 1; const x = 2;"));
+        assert!(!resolve(&mut Resolver::new(), "const x = 1; var x = 2;"));
+        assert!(!resolve(&mut Resolver::new(), "var x = 1; const x = 2;"));
+        assert!(resolve(&mut Resolver::new(), "var x = 1; var x = 2;"));
+        assert!(resolve(
+            &mut Resolver::new(),
+            "const x = 1; { const x = 2; }"
+        ));
+    }
+
+    #[test]
+    fn globals_persist() {
+        // like separate lines in the REPL
+        let mut r = Resolver::new();
+        assert!(run(&mut r, "const x = 1;"));
+        assert!(!resolve(&mut r, "x = 2;"));
+        assert!(!resolve(&mut r, "var x = 2;"));
+
+        // vars can become consts on a later line, though not in the same program
+        assert!(run(&mut r, "var v = 1;"));
+        assert!(run(&mut r, "const v = 2;"));
+        assert!(!resolve(&mut r, "v = 3;"));
+        assert!(!resolve(&mut r, "var w = 1; const w = 2;"));
+
+        // a program that fails to resolve doesn't declare anything
+        assert!(!run(&mut r, "const y = 1; x = 1;"));
+        assert!(resolve(&mut r, "var y = 2;"));
+
+        // nor does one that resolves but hasn't run, e.g. because it failed at runtime
+        assert!(resolve(&mut r, "const z = nope;"));
+        assert!(resolve(&mut r, "var z = 2;"));
+    }
+
+    #[test]
+    fn unreachable_match_arms() {
+        let warnings = |source: &str| {
+            let warnings = Resolver::new().resolve(&parse(source)).unwrap();
+            warnings.iter().map(|w| w.line()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            warnings("match x {\n _ => {}\n 1 => {}\n y => {}\n}"),
+            [2, 3]
+        );
+        assert_eq!(warnings("match x { 1 | _ => {} 2 => {} }"), [0]);
+        // guarded and refutable arms can fall through
+        assert!(warnings("match x { y if y => {} [_] => {} 1 => {} }").is_empty());
+    }
+
+    #[test]
+    fn or_pattern_bindings() {
+        let mut r = Resolver::new();
+        assert!(resolve(
+            &mut r,
+            "match x { [y, 1] | [1, y] | [y] => print y; }"
+        ));
+        assert!(resolve(&mut r, "match x { 1 | 2 | _ => {} }"));
+        assert!(!resolve(&mut r, "match x { 1 | y => print y; }"));
+        assert!(!resolve(&mut r, "match x { [y, _] | [_, z] => {} }"));
+        assert!(resolve(
+            &mut r,
+            "match x { P { a: y } | Q { y } => print y; }"
+        ));
+        assert!(!resolve(&mut r, "match x { P { a } | Q { y } => {} }"));
+    }
+}
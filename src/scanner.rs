@@ -1,5 +1,7 @@
 use std::iter::Peekable;
+use std::str::Chars;
 
+use crate::bigint::BigInt;
 use crate::error::{Error, ErrorState};
 use crate::token::{Token, TokenData};
 
@@ -10,9 +12,20 @@ pub fn scan(text: &str, starting_line: u32) -> Result<Vec<Token>, ErrorState> {
     let mut lineno = starting_line;
     let mut feed = text.chars().peekable();
 
+    // One entry per `${` we are currently inside, holding the line it's on and the number of
+    // unclosed braces within it. The `}` that closes the interpolation resumes the string.
+    let mut interpolations: Vec<(u32, u32)> = Vec::new();
+
     loop {
         let next = feed.next();
         if next.is_none() {
+            if let Some(&(line, _)) = interpolations.first() {
+                err_state.add(Error::scan_error(
+                    "unterminated string interpolation".to_string(),
+                    line,
+                ));
+            }
+
             tokens.push(Token::new(TokenData::Eof, lineno));
             break;
         }
@@ -22,72 +35,67 @@ pub fn scan(text: &str, starting_line: u32) -> Result<Vec<Token>, ErrorState> {
             // Unambiguous single character
             '(' => tokens.push(Token::new(TokenData::LeftParen, lineno)),
             ')' => tokens.push(Token::new(TokenData::RightParen, lineno)),
-            '{' => tokens.push(Token::new(TokenData::LeftBrace, lineno)),
-            '}' => tokens.push(Token::new(TokenData::RightBrace, lineno)),
+            '[' => tokens.push(Token::new(TokenData::LeftBracket, lineno)),
+            ']' => tokens.push(Token::new(TokenData::RightBracket, lineno)),
             ',' => tokens.push(Token::new(TokenData::Comma, lineno)),
-            '.' => tokens.push(Token::new(TokenData::Dot, lineno)),
-            '-' => tokens.push(Token::new(TokenData::Minus, lineno)),
-            '+' => tokens.push(Token::new(TokenData::Plus, lineno)),
+            ':' => tokens.push(Token::new(TokenData::Colon, lineno)),
             ';' => tokens.push(Token::new(TokenData::Semicolon, lineno)),
-            '*' => tokens.push(Token::new(TokenData::Star, lineno)),
 
-            // Single or double character operators
-            '!' => {
-                let t = double_char_ternary(&mut feed, '=', TokenData::BangEqual, TokenData::Bang);
-                tokens.push(Token::new(t, lineno));
-            }
-            '=' => {
-                let t =
-                    double_char_ternary(&mut feed, '=', TokenData::EqualEqual, TokenData::Equal);
-                tokens.push(Token::new(t, lineno));
-            }
-            '>' => {
-                let t = double_char_ternary(
-                    &mut feed,
-                    '=',
-                    TokenData::GreaterEqual,
-                    TokenData::Greater,
-                );
-                tokens.push(Token::new(t, lineno));
+            // Comments
+            '/' if feed.peek() == Some(&'/') => {
+                // consume second slash
+                feed.next();
+
+                // discard comment string
+                if let Err(e) = consume_until(&mut feed, '\n') {
+                    err_state.add(e);
+                    break;
+                    // todo - is this recoverable?
+                }
             }
-            '<' => {
-                let t = double_char_ternary(&mut feed, '=', TokenData::LessEqual, TokenData::Less);
-                tokens.push(Token::new(t, lineno));
+            '/' if feed.peek() == Some(&'*') => {
+                feed.next();
+
+                if let Err(e) = consume_block_comment(&mut feed, &mut lineno) {
+                    err_state.add(e);
+                    break;
+                }
             }
 
-            // Slashes & comments
-            '/' => {
-                if let Some('/') = feed.peek() {
-                    // consume second slash
-                    feed.next();
-
-                    // discard comment string
-                    if let Err(e) = consume_until(&mut feed, '\n') {
-                        err_state.add(e);
-                        break;
-                        // todo - is this recoverable?
-                    }
-                } else {
-                    tokens.push(Token::new(TokenData::Slash, lineno));
+            // braces, which need to be tracked to find the end of an interpolated expression
+            '{' => {
+                if let Some((_, depth)) = interpolations.last_mut() {
+                    *depth += 1;
                 }
+                tokens.push(Token::new(TokenData::LeftBrace, lineno));
             }
+            '}' => match interpolations.last_mut() {
+                Some((_, 0)) => {
+                    interpolations.pop();
 
-            // string literals
-            '"' => {
-                let literal = match consume_until(&mut feed, '"') {
-                    Ok(v) => v,
-                    Err(e) => {
-                        err_state.add(e);
-                        break;
-                        // todo - is this recoverable?
+                    // nothing between the `${` and this `}`
+                    if let Some(TokenData::Interpolation(_)) = tokens.last().map(|t| &t.data) {
+                        err_state.add(Error::scan_error("empty interpolation".to_string(), lineno));
                     }
-                };
 
-                tokens.push(Token::new(TokenData::StringToken(literal), lineno));
+                    // back inside the string
+                    match scan_string_segment(&mut feed, &mut lineno, &mut interpolations) {
+                        Ok(t) => tokens.push(t),
+                        Err(e) => err_state.add(e),
+                    }
+                }
+                Some((_, depth)) => {
+                    *depth -= 1;
+                    tokens.push(Token::new(TokenData::RightBrace, lineno));
+                }
+                None => tokens.push(Token::new(TokenData::RightBrace, lineno)),
+            },
 
-                // consume closing quote
-                feed.next();
-            }
+            // string literals
+            '"' => match scan_string_segment(&mut feed, &mut lineno, &mut interpolations) {
+                Ok(t) => tokens.push(t),
+                Err(e) => err_state.add(e),
+            },
 
             // ignore whitespace
             ' ' | '\r' | '\t' => (),
@@ -97,34 +105,21 @@ pub fn scan(text: &str, starting_line: u32) -> Result<Vec<Token>, ErrorState> {
 
             // fallthrough: need to call a fn on c
             c => {
-                // todo: should bail out of number parsing if the char after the `.` is not a digit
-                if c.is_ascii_digit() {
-                    let mut acc = vec![c];
-                    let part_two =
-                        match consume_while(&mut feed, |c| c.is_ascii_digit() || c == '.') {
-                            Ok(v) => v,
-                            Err(e) => {
-                                err_state.add(e);
-                                break;
-                                // todo - is this recoverable?
-                            }
-                        };
-
-                    acc.extend(part_two.iter());
-                    let word = acc.iter().collect::<String>();
-
-                    match word.parse() {
-                        Ok(n) => tokens.push(Token::new(TokenData::Number(n), lineno)),
-                        Err(e) => {
+                if let Some(t) = scan_operator(c, &mut feed) {
+                    tokens.push(Token::new(t, lineno));
+                } else if c.is_ascii_digit() {
+                    match scan_number(c, &mut feed) {
+                        Ok(t) => tokens.push(Token::new(t, lineno)),
+                        Err(word) => {
                             err_state.add(Error::scan_error(
-                                format!("invalid number literal: {word}, {e}"),
+                                format!("invalid number literal: {word}"),
                                 lineno,
                             ));
                         }
                     }
-                } else if is_word(c) {
+                } else if is_word_start(c) {
                     let mut acc = vec![c];
-                    let part_two = match consume_while(&mut feed, is_word) {
+                    let part_two = match consume_while(&mut feed, is_word_continue) {
                         Ok(v) => v,
                         Err(e) => {
                             err_state.add(e);
@@ -157,28 +152,350 @@ pub fn scan(text: &str, starting_line: u32) -> Result<Vec<Token>, ErrorState> {
         }
     }
 
-    Ok(tokens)
+    if err_state.is_ok() {
+        Ok(tokens)
+    } else {
+        Err(err_state)
+    }
 }
 
 // todo: this should probably own a Scanner object that holds the state, rather than having a bunch
 // of these helper functions that take a &mut Peekable (Vec iterator).
 
-// mutates feed if the condition is met to consume the second character
-// condition : result ? else
-fn double_char_ternary<I: Iterator<Item = char>>(
+// Number literals are integers in decimal, hex (`0x`) or binary (`0b`), or decimal floats with a
+// fraction and/or exponent. Digits may be separated with underscores. On failure, returns the
+// malformed literal.
+fn scan_number(first: char, feed: &mut Peekable<Chars>) -> Result<TokenData, String> {
+    let mut literal = first.to_string();
+
+    let radix = match (first, feed.peek()) {
+        ('0', Some('x' | 'X')) => 16,
+        ('0', Some('b' | 'B')) => 2,
+        _ => 10,
+    };
+
+    let number = if radix != 10 {
+        literal.push(feed.next().unwrap());
+
+        let run = consume_digits(feed, radix);
+        literal.push_str(&run);
+
+        strip_separators(&run).and_then(|digits| parse_integer(&digits, radix))
+    } else {
+        let mut is_float = false;
+
+        let run = consume_digits(feed, 10);
+        literal.push_str(&run);
+        let mut number = strip_separators(&format!("{first}{run}"));
+
+        // Only treat the dot as a decimal point if a digit follows, so that `1.foo` scans as a
+        // number followed by a method access.
+        let mut lookahead = feed.clone();
+        if lookahead.next() == Some('.') && lookahead.peek().is_some_and(char::is_ascii_digit) {
+            is_float = true;
+            feed.next();
+
+            let run = consume_digits(feed, 10);
+            literal.push_str(&format!(".{run}"));
+            number = number
+                .zip(strip_separators(&run))
+                .map(|(int, fraction)| format!("{int}.{fraction}"));
+        }
+
+        if let Some(&e @ ('e' | 'E')) = feed.peek() {
+            is_float = true;
+            feed.next();
+            literal.push(e);
+
+            let mut sign = String::new();
+            if let Some(&c @ ('+' | '-')) = feed.peek() {
+                feed.next();
+                sign.push(c);
+                literal.push(c);
+            }
+
+            let run = consume_digits(feed, 10);
+            literal.push_str(&run);
+            number = number
+                .zip(strip_separators(&run))
+                .map(|(mantissa, exponent)| format!("{mantissa}e{sign}{exponent}"));
+        }
+
+        if is_float {
+            number.and_then(|n| n.parse().ok().map(TokenData::Number))
+        } else {
+            number.and_then(|n| parse_integer(&n, 10))
+        }
+    };
+
+    // A literal running straight into more digits, letters or a second decimal point (`1.2.3`,
+    // `12abc`, `0b102`) is malformed as a whole.
+    let mut lookahead = feed.clone();
+    let runs_on = match lookahead.next() {
+        Some('.') => lookahead.peek().is_some_and(char::is_ascii_digit),
+        Some(c) => is_word_continue(c),
+        None => false,
+    };
+
+    if runs_on {
+        let rest = consume_while(feed, |c| is_word_continue(c) || c == '.').unwrap_or_default();
+        literal.extend(rest);
+        return Err(literal);
+    }
+
+    number.ok_or(literal)
+}
+
+// Consumes the rest of a block comment after the opening `/*`, including any nested block comments.
+// Newlines inside the comment advance lineno. An unterminated comment is reported at the line of
+// the opening `/*`.
+fn consume_block_comment(feed: &mut Peekable<Chars>, lineno: &mut u32) -> Result<(), Error> {
+    let start_line = *lineno;
+    let mut depth = 1;
+
+    while depth > 0 {
+        match (feed.next(), feed.peek()) {
+            (Some('/'), Some('*')) => {
+                feed.next();
+                depth += 1;
+            }
+            (Some('*'), Some('/')) => {
+                feed.next();
+                depth -= 1;
+            }
+            (Some('\n'), _) => *lineno += 1,
+            (Some(_), _) => (),
+            (None, _) => {
+                return Err(Error::scan_error(
+                    "unterminated block comment".to_string(),
+                    start_line,
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// How a piece of a string literal ended.
+enum StringEnd {
+    // the closing quote
+    Quote,
+    // the `${` opening an interpolated expression
+    Interpolation,
+}
+
+// Scans a string literal, or the part of one following an interpolated expression, into a token.
+//
+// `"a ${b} c"` scans as `Interpolation("a ")`, the tokens of `b`, then `StringToken(" c")`.
+fn scan_string_segment(
+    feed: &mut Peekable<Chars>,
+    lineno: &mut u32,
+    interpolations: &mut Vec<(u32, u32)>,
+) -> Result<Token, Error> {
+    let start_line = *lineno;
+    let (literal, end) = scan_string(feed, lineno);
+
+    let t = match end {
+        StringEnd::Quote => TokenData::StringToken(literal?),
+        StringEnd::Interpolation => {
+            // push even if there was an error, so the closing `}` is still matched up
+            interpolations.push((*lineno, 0));
+            TokenData::Interpolation(literal?)
+        }
+    };
+
+    Ok(Token::new(t, start_line))
+}
+
+// Scans the rest of a string literal up to and including the closing quote or the next `${`.
+// Newlines inside the string advance lineno.
+//
+// An unknown escape sequence is reported after the rest of the string is consumed, so scanning can
+// continue after it. An unterminated string is reported at the line it started on.
+fn scan_string(feed: &mut Peekable<Chars>, lineno: &mut u32) -> (Result<String, Error>, StringEnd) {
+    let start_line = *lineno;
+    let mut literal = String::new();
+    let mut err = None;
+
+    let end = loop {
+        let c = match feed.next() {
+            Some(c) => c,
+            None => {
+                let e = Error::scan_error("unterminated string".to_string(), start_line);
+                return (Err(e), StringEnd::Quote);
+            }
+        };
+
+        match c {
+            '"' => break StringEnd::Quote,
+            '$' if feed.peek() == Some(&'{') => {
+                feed.next();
+                break StringEnd::Interpolation;
+            }
+            '\n' => {
+                *lineno += 1;
+                literal.push(c);
+            }
+            '\\' => match scan_escape(feed) {
+                Ok(c) => literal.push(c),
+                Err(msg) => {
+                    err.get_or_insert(Error::scan_error(msg, *lineno));
+                }
+            },
+            c => literal.push(c),
+        }
+    };
+
+    match err {
+        Some(e) => (Err(e), end),
+        None => (Ok(literal), end),
+    }
+}
+
+// Translates the escape sequence following a backslash.
+fn scan_escape(feed: &mut Peekable<Chars>) -> Result<char, String> {
+    let c = match feed.peek() {
+        Some(&c) => c,
+        None => return Err("unterminated escape sequence".to_string()),
+    };
+
+    let escaped = match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '$' => '$',
+        'u' => {
+            feed.next();
+
+            // \u{XXXX}: one to six hex digits naming a unicode scalar value
+            if feed.peek() != Some(&'{') {
+                return Err("expected { after \\u".to_string());
+            }
+            feed.next();
+
+            let digits = consume_while(feed, |c| c != '}' && c != '"')
+                .unwrap_or_default()
+                .iter()
+                .collect::<String>();
+            if feed.peek() != Some(&'}') {
+                return Err(format!("unterminated unicode escape: \\u{{{digits}"));
+            }
+            feed.next();
+
+            return u32::from_str_radix(&digits, 16)
+                .ok()
+                .filter(|_| digits.len() <= 6)
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid unicode escape: \\u{{{digits}}}"));
+        }
+        // Leave newlines and quotes to scan_string so that line counting and termination still
+        // work after a bad escape.
+        '\n' => return Err("unknown escape sequence: \\ followed by a newline".to_string()),
+        c => {
+            feed.next();
+            return Err(format!("unknown escape sequence: \\{c}"));
+        }
+    };
+
+    feed.next();
+    Ok(escaped)
+}
+
+// Consumes a run of digits in the given radix, including underscore separators.
+fn consume_digits(feed: &mut Peekable<Chars>, radix: u32) -> String {
+    consume_while(feed, |c| c.is_digit(radix) || c == '_')
+        .unwrap_or_default()
+        .iter()
+        .collect()
+}
+
+// Removes the separators from a run of digits. Returns None if the run is empty or a separator
+// isn't between two digits.
+fn strip_separators(run: &str) -> Option<String> {
+    if run.is_empty() || run.starts_with('_') || run.ends_with('_') || run.contains("__") {
+        return None;
+    }
+
+    Some(run.replace('_', ""))
+}
+
+// Integers that don't fit in an i64 are arbitrary-precision.
+fn parse_integer(digits: &str, radix: u32) -> Option<TokenData> {
+    match i64::from_str_radix(digits, radix) {
+        Ok(n) => Some(TokenData::Integer(n)),
+        Err(_) => BigInt::parse_radix(digits, radix).map(TokenData::BigInteger),
+    }
+}
+
+// Operators made of punctuation, matched greedily: if the first character can start a two
+// character operator and the next one completes it, the second character is consumed too. Returns
+// None if the character doesn't start an operator.
+fn scan_operator<I: Iterator<Item = char>>(
+    first: char,
     feed: &mut Peekable<I>,
-    conditional_match: char,
-    if_true: TokenData,
-    if_false: TokenData,
-) -> TokenData {
-    if let Some(&c) = feed.peek() {
-        if c == conditional_match {
+) -> Option<TokenData> {
+    use TokenData::*;
+
+    let double = feed.peek().and_then(|&second| {
+        let t = match (first, second) {
+            ('!', '=') => BangEqual,
+            ('=', '=') => EqualEqual,
+            ('=', '>') => Arrow,
+            ('<', '=') => LessEqual,
+            ('<', '<') => LessLess,
+            ('>', '=') => GreaterEqual,
+            ('>', '>') => GreaterGreater,
+            ('+', '+') => PlusPlus,
+            ('-', '-') => MinusMinus,
+            ('+', '=') => PlusEqual,
+            ('-', '=') => MinusEqual,
+            ('*', '=') => StarEqual,
+            ('/', '=') => SlashEqual,
+            ('*', '*') => StarStar,
+            // floor division, since `//` starts a comment
+            ('~', '/') => TildeSlash,
+            ('.', '.') => DotDot,
+            _ => return None,
+        };
+        Some(t)
+    });
+
+    if let Some(t) = double {
+        feed.next();
+
+        // the only three character operator
+        if t == DotDot && feed.peek() == Some(&'.') {
             feed.next();
-            return if_true;
+            return Some(Ellipsis);
         }
+
+        return Some(t);
     }
 
-    if_false
+    let single = match first {
+        '!' => Bang,
+        '=' => Equal,
+        '<' => Less,
+        '>' => Greater,
+        '+' => Plus,
+        '-' => Minus,
+        '*' => Star,
+        '/' => Slash,
+        '%' => Percent,
+        '&' => Ampersand,
+        '|' => Pipe,
+        '^' => Caret,
+        '~' => Tilde,
+        '?' => Question,
+        '.' => Dot,
+        _ => return None,
+    };
+
+    Some(single)
 }
 
 // doesn't consume final character
@@ -209,29 +526,48 @@ fn consume_while<I: Iterator<Item = char>, F: Fn(char) -> bool>(
     Ok(acc)
 }
 
-// keywords or identifier literals
-fn is_word(c: char) -> bool {
-    c.is_ascii_uppercase() || c.is_ascii_lowercase() || c == '_'
+// keywords or identifier literals, following Unicode's XID_Start and XID_Continue classes (UAX #31)
+fn is_word_start(c: char) -> bool {
+    unicode_ident::is_xid_start(c) || c == '_'
+}
+
+fn is_word_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
 }
 
 fn match_keyword(s: String) -> Result<TokenData, Error> {
     let t = match s.as_str() {
         "and" => TokenData::And,
+        "as" => TokenData::As,
+        "break" => TokenData::Break,
+        "catch" => TokenData::Catch,
         "class" => TokenData::Class,
+        "const" => TokenData::Const,
+        "continue" => TokenData::Continue,
         "else" => TokenData::Else,
+        "export" => TokenData::Export,
         "false" => TokenData::False,
+        "finally" => TokenData::Finally,
         "fun" => TokenData::Fun,
         "for" => TokenData::For,
         "if" => TokenData::If,
+        "import" => TokenData::Import,
+        "in" => TokenData::In,
+        "match" => TokenData::Match,
         "nil" => TokenData::Nil,
         "or" => TokenData::Or,
         "print" => TokenData::Print,
         "return" => TokenData::Return,
         "super" => TokenData::Super,
         "this" => TokenData::This,
+        "throw" => TokenData::Throw,
+        "trait" => TokenData::Trait,
         "true" => TokenData::True,
+        "try" => TokenData::Try,
         "var" => TokenData::Var,
         "while" => TokenData::While,
+        "with" => TokenData::With,
+        "yield" => TokenData::Yield,
 
         _ => TokenData::Identifier(s),
     };
@@ -241,7 +577,11 @@ fn match_keyword(s: String) -> Result<TokenData, Error> {
 
 #[cfg(test)]
 #[rustfmt::skip]
+// tokens! builds its Vec one push at a time
+#[allow(clippy::vec_init_then_push)]
 mod tests {
+    use crate::bigint::BigInt;
+    use crate::error::ErrorState;
     use crate::token::{Token, TokenData::*};
     use crate::tokens;
 
@@ -267,12 +607,16 @@ mod tests {
     #[test]
     fn singles() {
         assert_tokens!(
-            "( { } )
-            , . - + ; / *",
+            "( { [ ] } )
+            , : . - + ; ? / * %
+            & | ^ ~",
             tokens![
-                (LeftParen, 0), (LeftBrace, 0), (RightBrace, 0), (RightParen, 0),
-                (Comma, 1), (Dot, 1), (Minus, 1), (Plus, 1), (Semicolon, 1), (Slash, 1), (Star, 1),
-                (Eof, 1),
+                (LeftParen, 0), (LeftBrace, 0), (LeftBracket, 0),
+                (RightBracket, 0), (RightBrace, 0), (RightParen, 0),
+                (Comma, 1), (Colon, 1), (Dot, 1), (Minus, 1), (Plus, 1), (Semicolon, 1), (Question, 1), (Slash, 1), (Star, 1),
+                (Percent, 1),
+                (Ampersand, 2), (Pipe, 2), (Caret, 2), (Tilde, 2),
+                (Eof, 2),
             ]
         );
     }
@@ -281,15 +625,25 @@ mod tests {
     fn doubles() {
         assert_tokens!(
             "! !=
-            = ==
+            = == =>
             > >=
-            < <=",
+            < <=
+            * ** ~/
+            << >>
+            + ++ += - -- -= *= /=
+            . .. ...",
             tokens![
                 (Bang, 0),    (BangEqual, 0),
-                (Equal, 1),   (EqualEqual, 1),
+                (Equal, 1),   (EqualEqual, 1), (Arrow, 1),
                 (Greater, 2), (GreaterEqual, 2),
                 (Less, 3),    (LessEqual, 3),
-                (Eof, 3),
+                (Star, 4),    (StarStar, 4),   (TildeSlash, 4),
+                (LessLess, 5), (GreaterGreater, 5),
+                (Plus, 6),    (PlusPlus, 6),   (PlusEqual, 6),
+                (Minus, 6),   (MinusMinus, 6), (MinusEqual, 6),
+                (StarEqual, 6), (SlashEqual, 6),
+                (Dot, 7),     (DotDot, 7),     (Ellipsis, 7),
+                (Eof, 7),
             ]
         );
     }
@@ -304,13 +658,203 @@ mod tests {
             tokens![
                 (Identifier("id".to_string()), 0),
                 (StringToken("literal".to_string()), 1),
-                (Number(123.0), 2),
+                (Integer(123), 2),
                 (Number(4.0), 3),
                 (Eof, 3),
             ]
         );
     }
 
+    #[test]
+    fn large_numbers() {
+        // not representable as f32
+        assert_tokens!(
+            "16777217.0
+            9007199254740992.0
+            0.1",
+            tokens![
+                (Number(16777217.0), 0),
+                (Number(9007199254740992.0), 1),
+                (Number(0.1), 2),
+                (Eof, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn integers() {
+        // not representable as f64
+        assert_tokens!(
+            "0
+            9007199254740993
+            9223372036854775807",
+            tokens![
+                (Integer(0), 0),
+                (Integer(9007199254740993), 1),
+                (Integer(i64::MAX), 2),
+                (Eof, 2),
+            ]
+        );
+
+        assert_tokens!(
+            "9223372036854775808",
+            tokens![
+                (BigInteger(BigInt::parse_radix("9223372036854775808", 10).unwrap()), 0),
+                (Eof, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn number_formats() {
+        assert_tokens!(
+            "0xFF 0Xff 0b101 0B0
+            1_000_000 0xFF_FF 1.5_0
+            1e10 1E+2 2.5e-3 1_0e1_0
+            0xFFFFFFFFFFFFFFFFFF",
+            tokens![
+                (Integer(255), 0), (Integer(255), 0), (Integer(5), 0), (Integer(0), 0),
+                (Integer(1000000), 1), (Integer(65535), 1), (Number(1.5), 1),
+                (Number(1e10), 2), (Number(100.0), 2), (Number(2.5e-3), 2), (Number(10e10), 2),
+                (BigInteger(BigInt::parse_radix("FFFFFFFFFFFFFFFFFF", 16).unwrap()), 3),
+                (Eof, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_dot() {
+        // the dot is only a decimal point when followed by a digit
+        assert_tokens!(
+            "1. 1.foo 0..10",
+            tokens![
+                (Integer(1), 0), (Dot, 0),
+                (Integer(1), 0), (Dot, 0), (Identifier("foo".to_string()), 0),
+                (Integer(0), 0), (DotDot, 0), (Integer(10), 0),
+                (Eof, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for s in ["1.2.3", "12abc", "0x", "0xG", "0b102", "1e", "1e+", "1__0", "1_", "1_.5"] {
+            assert!(scan(s, 0).is_err(), "{s} should not scan");
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_tokens!(
+            r#""a\nb\t\r\0\\ \"quoted\" \u{1F600} \u{e9}""#,
+            tokens![
+                (StringToken("a\nb\t\r\0\\ \"quoted\" \u{1F600} \u{e9}".to_string()), 0),
+                (Eof, 0),
+            ]
+        );
+
+        for s in [r#""\q""#, r#""\u""#, r#""\u{}""#, r#""\u{110000}""#, r#""\u{D800}""#, r#""\u{41""#] {
+            assert!(scan(s, 0).is_err(), "{s} should not scan");
+        }
+    }
+
+    #[test]
+    fn multiline_strings() {
+        // tokens after a multi-line string have the right line number
+        assert_tokens!(
+            "\"one
+            two\" three",
+            tokens![
+                (StringToken("one\n            two".to_string()), 0),
+                (Identifier("three".to_string()), 1),
+                (Eof, 1),
+            ]
+        );
+
+        assert!(scan("\"unterminated", 0).is_err());
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_tokens!(
+            "/* one line */ +
+            /* two
+            lines */ -
+            /* nested /* comments
+            */ still ignored */ *
+            /**/ / /*/ ignored */",
+            tokens![
+                (Plus, 0),
+                (Minus, 2),
+                (Star, 4),
+                (Slash, 5),
+                (Eof, 5),
+            ]
+        );
+
+        assert!(scan("/* unterminated", 0).is_err());
+        assert!(scan("/* /* nested */ unterminated", 0).is_err());
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_tokens!(
+            "x1 _tmp2 café 変数 Δx",
+            tokens![
+                (Identifier("x1".to_string()), 0),
+                (Identifier("_tmp2".to_string()), 0),
+                (Identifier("café".to_string()), 0),
+                (Identifier("変数".to_string()), 0),
+                (Identifier("Δx".to_string()), 0),
+                (Eof, 0),
+            ]
+        );
+
+        // not identifier characters
+        assert!(scan("😀", 0).is_err());
+        assert!(scan("12é", 0).is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_tokens!(
+            r#""Hello ${name}, you are ${age + 1}\${}""#,
+            tokens![
+                (Interpolation("Hello ".to_string()), 0),
+                (Identifier("name".to_string()), 0),
+                (Interpolation(", you are ".to_string()), 0),
+                (Identifier("age".to_string()), 0), (Plus, 0), (Integer(1), 0),
+                (StringToken("${}".to_string()), 0),
+                (Eof, 0),
+            ]
+        );
+
+        // nested strings and braces inside the expression
+        assert_tokens!(
+            r#""a${ {"k": "b${c}"}["k"] }d" "#,
+            tokens![
+                (Interpolation("a".to_string()), 0),
+                (LeftBrace, 0),
+                (StringToken("k".to_string()), 0), (Colon, 0),
+                (Interpolation("b".to_string()), 0),
+                (Identifier("c".to_string()), 0),
+                (StringToken("".to_string()), 0),
+                (RightBrace, 0),
+                (LeftBracket, 0), (StringToken("k".to_string()), 0), (RightBracket, 0),
+                (StringToken("d".to_string()), 0),
+                (Eof, 0),
+            ]
+        );
+
+        assert!(scan(r#""a ${b"#, 0).is_err());
+        assert!(scan(r#""a ${b} c"#, 0).is_err());
+
+        let Err(ErrorState::ScanErrs(errs)) = scan(r#""a ${ } c""#, 0) else {
+            panic!("expected a scan error");
+        };
+        assert_eq!(errs[0].to_string(), "[0]: scan error: empty interpolation");
+    }
+
     #[test]
     fn keywords() {
         assert_tokens!(
@@ -320,8 +864,15 @@ mod tests {
             class this super
             and or
             print
-            fun return
-            var nil",
+            fun return yield
+            var nil
+            break continue
+            try catch finally throw
+            match
+            import as export
+            const
+            trait with
+            for in",
             tokens![
                 (If, 0),    (Else, 0),
                 (For, 1),   (While, 1),
@@ -329,9 +880,16 @@ mod tests {
                 (Class, 3), (This, 3),  (Super, 3),
                 (And, 4),   (Or, 4),
                 (Print, 5),
-                (Fun, 6),   (Return, 6),
+                (Fun, 6),   (Return, 6), (Yield, 6),
                 (Var, 7),   (Nil, 7),
-                (Eof, 7)
+                (Break, 8), (Continue, 8),
+                (Try, 9),   (Catch, 9), (Finally, 9), (Throw, 9),
+                (Match, 10),
+                (Import, 11), (As, 11), (Export, 11),
+                (Const, 12),
+                (Trait, 13), (With, 13),
+                (For, 14),  (In, 14),
+                (Eof, 14)
             ]
         );
     }
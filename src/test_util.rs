@@ -0,0 +1,26 @@
+//! Helpers shared by the unit tests of the different modules.
+
+use crate::exec::{exec_block, Environment};
+use crate::expr::Program;
+use crate::resolver::Resolver;
+use crate::{parser, scanner};
+
+/// Scans and parses a program, panicking if it has errors.
+pub fn parse(source: &str) -> Program {
+    parser::parse(scanner::scan(source, 0).unwrap()).unwrap()
+}
+
+/// Resolves and runs a program, returning the global scope it leaves behind.
+pub fn run(source: &str) -> Environment {
+    let program = parse(source);
+    Resolver::new().resolve(&program).unwrap();
+
+    let mut env = Environment::new();
+    exec_block(&program, &mut env).unwrap();
+    env
+}
+
+/// The value of a global, as `print` would show it.
+pub fn global(env: &mut Environment, name: &str) -> String {
+    env.get(&name.to_string()).unwrap().to_string()
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use crate::bigint::BigInt;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub data: TokenData,
@@ -19,13 +21,22 @@ pub enum TokenData {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
     Plus,
     Semicolon,
+    Question,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -36,28 +47,60 @@ pub enum TokenData {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    Arrow,
+    PlusPlus,
+    MinusMinus,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    StarStar,
+    TildeSlash,
+    DotDot,
+    Ellipsis,
 
     Identifier(String),
     StringToken(String),
-    Number(f32),
+    // A piece of a string literal that is followed by an interpolated expression.
+    Interpolation(String),
+    Number(f64),
+    Integer(i64),
+    BigInteger(BigInt),
 
     // Keywords.
     And,
+    Break,
+    Catch,
     Class,
+    Const,
+    Continue,
     Else,
+    Export,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    In,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
+    Trait,
     True,
+    Try,
     Var,
+    As,
     While,
+    With,
+    Yield,
 
     Eof,
 }